@@ -1,15 +1,51 @@
 #[derive(Debug, Clone)]
 pub enum BinaryOperator {
-    Add,       // +
-    Subtract,  // -
-    Multiply,  // *
-    Divide,    // /
-    Greater,   // >
-    Less,      // <
-    Equal,     // ==
-    NotEqual,  // !=
-    GreaterEq, // >=
-    LessEq,    // <=
+    Add,          // +
+    Subtract,     // -
+    Multiply,     // *
+    Divide,       // /
+    Modulo,       // %
+    Greater,      // >
+    Less,         // <
+    Equal,        // ==
+    NotEqual,     // !=
+    GreaterEq,    // >=
+    LessEq,       // <=
+    NullCoalesce, // ??
+}
+
+impl BinaryOperator {
+    /// Binding power used when folding a flat `BinaryExpression`; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::NullCoalesce => 1,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 2,
+            BinaryOperator::Greater
+            | BinaryOperator::Less
+            | BinaryOperator::GreaterEq
+            | BinaryOperator::LessEq => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 5,
+        }
+    }
+
+    /// The operator as written in source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::Less => "<",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::GreaterEq => ">=",
+            BinaryOperator::LessEq => "<=",
+            BinaryOperator::NullCoalesce => "??",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +54,47 @@ pub enum UnaryOperator {
     Not,   // !
 }
 
+impl UnaryOperator {
+    /// The operator as written in source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperator::Minus => "-",
+            UnaryOperator::Not => "!",
+        }
+    }
+}
+
+/// A region of the source file: byte offsets `start..end`, plus the 1-based
+/// line and column of `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(self);
+        }
+        Span {
+            end: self.end.max(other.end),
+            ..self
+        }
+    }
+}
+
+/// One entry of a `DefArgList`: `name`, `name = default` or `...name`.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub default: Option<Box<AstNode>>,
+    pub rest: bool,
+}
+
 #[derive(Debug, Clone)]
 pub enum AstNode {
     Program(Vec<Box<AstNode>>),
@@ -26,13 +103,21 @@ pub enum AstNode {
         args: Box<AstNode>,
         body: Box<AstNode>,
     },
-    Block(Vec<Box<AstNode>>),
+    /// `{ statements... tail }`; the block evaluates to `tail`, an expression
+    /// without a trailing `;`, or to `None` when there is none.
+    Block {
+        statements: Vec<Box<AstNode>>,
+        tail: Option<Box<AstNode>>,
+    },
     VarDecl {
         name: String,
         value: Option<Box<AstNode>>,
     },
+    /// `target = value`, or `target op= value` when `op` is set. The target is an
+    /// `Identifier` or an `Index` chain on one.
     VarSet {
-        name: String,
+        target: Box<AstNode>,
+        op: Option<BinaryOperator>,
         value: Box<AstNode>,
     },
     FuncCall {
@@ -44,6 +129,11 @@ pub enum AstNode {
         params: Box<AstNode>,
         body: Box<AstNode>,
     },
+    ForInLoop {
+        names: Vec<String>,
+        iterable: Box<AstNode>,
+        body: Box<AstNode>,
+    },
     ForLoopParams {
         initialization: Box<AstNode>,
         condition: Box<AstNode>,
@@ -56,9 +146,21 @@ pub enum AstNode {
     IfStatement {
         condition: Box<AstNode>,
         body: Box<AstNode>,
+        else_body: Option<Box<AstNode>>,
+    },
+    Yield(Box<AstNode>),
+    Throw(Box<AstNode>),
+    TryCatch {
+        body: Box<AstNode>,
+        catch: Option<(String, Box<AstNode>)>,
+        finally: Option<Box<AstNode>>,
     },
-    DefArgList(Vec<String>),
+    DefArgList(Vec<Parameter>),
     ArgList(Vec<Box<AstNode>>),
+    NamedArg {
+        name: String,
+        value: Box<AstNode>,
+    },
     Expression(Box<AstNode>),
     BinaryExpression {
         lhs: Box<AstNode>,
@@ -70,8 +172,133 @@ pub enum AstNode {
         child: Box<AstNode>,
     },
     Term(Box<AstNode>),
+    Propagate(Box<AstNode>),
+    Index {
+        target: Box<AstNode>,
+        index: Box<AstNode>,
+    },
+    Range {
+        start: Box<AstNode>,
+        end: Box<AstNode>,
+        inclusive: bool,
+        step: Option<Box<AstNode>>,
+    },
+    List(Vec<Box<AstNode>>),
     Int(i32),
     Str(String),
     Identifier(String),
     Boolean(bool),
+    Nil,
+    /// `node` together with the source it was parsed from. The parser wraps every
+    /// statement, expression, block and function definition this way; runtime
+    /// errors report the innermost enclosing span.
+    Spanned(Span, Box<AstNode>),
+}
+
+impl AstNode {
+    /// The nodes directly below this one, in source order.
+    pub fn children(&self) -> Vec<&AstNode> {
+        let mut children: Vec<&AstNode> = vec![];
+        match self {
+            AstNode::Program(nodes) | AstNode::ArgList(nodes) | AstNode::List(nodes) => {
+                children.extend(nodes.iter().map(|n| &**n))
+            }
+            AstNode::FuncDef { args, body, .. } => children.extend([&**args, &**body]),
+            AstNode::Block { statements, tail } => {
+                children.extend(statements.iter().chain(tail).map(|n| &**n))
+            }
+            AstNode::VarDecl { value, .. } => children.extend(value.as_deref()),
+            AstNode::VarSet { target, value, .. } => children.extend([&**target, &**value]),
+            AstNode::FuncCall { args, .. } => children.push(args),
+            AstNode::FuncReturn(child)
+            | AstNode::Yield(child)
+            | AstNode::Throw(child)
+            | AstNode::Expression(child)
+            | AstNode::Term(child)
+            | AstNode::Propagate(child)
+            | AstNode::UnaryExpression { child, .. }
+            | AstNode::NamedArg { value: child, .. }
+            | AstNode::Spanned(_, child) => children.push(child),
+            AstNode::ForLoop { params, body } => children.extend([&**params, &**body]),
+            AstNode::ForInLoop { iterable, body, .. } => children.extend([&**iterable, &**body]),
+            AstNode::ForLoopParams { initialization, condition, updater } => {
+                children.extend([&**initialization, &**condition, &**updater])
+            }
+            AstNode::WhileLoop { condition, body } => children.extend([&**condition, &**body]),
+            AstNode::IfStatement { condition, body, else_body } => {
+                children.extend([&**condition, &**body]);
+                children.extend(else_body.as_deref());
+            }
+            AstNode::TryCatch { body, catch, finally } => {
+                children.push(body);
+                children.extend(catch.as_ref().map(|(_, handler)| &**handler));
+                children.extend(finally.as_deref());
+            }
+            AstNode::DefArgList(params) => {
+                children.extend(params.iter().filter_map(|param| param.default.as_deref()))
+            }
+            AstNode::BinaryExpression { lhs, rhs, .. } => children.extend([&**lhs, &**rhs]),
+            AstNode::Index { target, index } => children.extend([&**target, &**index]),
+            AstNode::Range { start, end, step, .. } => {
+                children.extend([&**start, &**end]);
+                children.extend(step.as_deref());
+            }
+            AstNode::Int(_) | AstNode::Str(_) | AstNode::Identifier(_) | AstNode::Boolean(_) | AstNode::Nil => {}
+        }
+        children
+    }
+
+    /// Where this node came from in the source, if it was recorded.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            AstNode::Spanned(span, _) => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// The node inside a `Spanned` wrapper, or `self` when there is none.
+    pub fn unspanned(&self) -> &AstNode {
+        match self {
+            AstNode::Spanned(_, inner) => inner,
+            other => other,
+        }
+    }
+
+    /// Peels the `Expression`/`Term`/`Spanned` wrappers the parser leaves around expressions.
+    pub fn unwrap_expression(&self) -> &AstNode {
+        match self {
+            AstNode::Expression(inner) | AstNode::Term(inner) | AstNode::Spanned(_, inner) => {
+                inner.unwrap_expression()
+            }
+            other => other,
+        }
+    }
+
+    /// Whether a `yield` statement is reachable from this statement without
+    /// entering another function, i.e. whether it makes its function a generator.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            AstNode::Yield(_) => true,
+            AstNode::Spanned(_, inner) => inner.contains_yield(),
+            AstNode::Expression(_) | AstNode::Term(_) => match self.unwrap_expression() {
+                block @ AstNode::Block { .. } => block.contains_yield(),
+                _ => false,
+            },
+            AstNode::Block { statements, tail } => {
+                statements.iter().chain(tail).any(|s| s.contains_yield())
+            }
+            AstNode::IfStatement { body, else_body, .. } => {
+                body.contains_yield() || else_body.as_ref().is_some_and(|e| e.contains_yield())
+            }
+            AstNode::WhileLoop { body, .. }
+            | AstNode::ForLoop { body, .. }
+            | AstNode::ForInLoop { body, .. } => body.contains_yield(),
+            AstNode::TryCatch { body, catch, finally } => {
+                body.contains_yield()
+                    || catch.as_ref().is_some_and(|(_, handler)| handler.contains_yield())
+                    || finally.as_ref().is_some_and(|finally| finally.contains_yield())
+            }
+            _ => false,
+        }
+    }
 }
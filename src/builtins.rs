@@ -1,64 +1,361 @@
-use crate::interpreter::Value;
+use crate::interpreter::{ErrorKind, Evaluator, RuntimeError, Unwind, Value};
+use crate::iterator::Iter;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{stdin, stdout, Write};
-pub type BuiltinFunction = fn(Vec<Value>) -> Result<Value, String>;
+pub type BuiltinFunction = fn(&mut Evaluator, Vec<Value>) -> Result<Value, Unwind>;
 pub struct Builtins {
     pub functions: HashMap<String, BuiltinFunction>
 }
+impl Default for Builtins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Builtins {
     pub fn new() -> Self {
         let mut functions = HashMap::new();
         functions.insert("print".to_string(), Builtins::print as BuiltinFunction);
         functions.insert("input".to_string(), Builtins::input as BuiltinFunction);
+        functions.insert("exit".to_string(), Builtins::exit as BuiltinFunction);
         functions.insert("str".to_string(), Builtins::str as BuiltinFunction);
         functions.insert("int".to_string(), Builtins::int as BuiltinFunction);
+        functions.insert("is_none".to_string(), Builtins::is_none as BuiltinFunction);
+        functions.insert("error".to_string(), Builtins::error as BuiltinFunction);
+        functions.insert("error_kind".to_string(), Builtins::error_kind as BuiltinFunction);
+        functions.insert("error_message".to_string(), Builtins::error_message as BuiltinFunction);
+        functions.insert("Ok".to_string(), Builtins::ok as BuiltinFunction);
+        functions.insert("Err".to_string(), Builtins::err as BuiltinFunction);
+        functions.insert("is_ok".to_string(), Builtins::is_ok as BuiltinFunction);
+        functions.insert("is_err".to_string(), Builtins::is_err as BuiltinFunction);
+        functions.insert("unwrap".to_string(), Builtins::unwrap as BuiltinFunction);
+        functions.insert("try_int".to_string(), Builtins::try_int as BuiltinFunction);
+        functions.insert("len".to_string(), Builtins::len as BuiltinFunction);
+        functions.insert("push".to_string(), Builtins::push as BuiltinFunction);
+        functions.insert("dict".to_string(), Builtins::dict as BuiltinFunction);
+        functions.insert("insert".to_string(), Builtins::insert as BuiltinFunction);
+        functions.insert("iter".to_string(), Builtins::iter as BuiltinFunction);
+        functions.insert("next".to_string(), Builtins::next as BuiltinFunction);
+        functions.insert("enumerate".to_string(), Builtins::enumerate as BuiltinFunction);
+        functions.insert("map".to_string(), Builtins::map as BuiltinFunction);
+        functions.insert("filter".to_string(), Builtins::filter as BuiltinFunction);
+        functions.insert("take".to_string(), Builtins::take as BuiltinFunction);
+        functions.insert("skip".to_string(), Builtins::skip as BuiltinFunction);
+        functions.insert("zip".to_string(), Builtins::zip as BuiltinFunction);
+        functions.insert("chain".to_string(), Builtins::chain as BuiltinFunction);
+        functions.insert("collect".to_string(), Builtins::collect as BuiltinFunction);
+        functions.insert("sum".to_string(), Builtins::sum as BuiltinFunction);
+        functions.insert("count".to_string(), Builtins::count as BuiltinFunction);
         Builtins { functions }
     }
-    fn print(args: Vec<Value>) -> Result<Value, String> {
+    fn print(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
         for arg in args {
-            match arg {
-                Value::Integer(i) => print!("{}", i),
-                Value::String(s) => print!("{}", s),
-                Value::Boolean(b) => print!("{}", b),
-                _ => return Err("Unsupported type for print".to_string()),
-            }
+            print!("{}", arg);
         }
         println!();
-        Ok(Value::Integer(0))
+        Ok(Value::Nil)
     }
-    fn input(args: Vec<Value>) -> Result<Value, String> {
+    fn input(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
         for arg in args {
-            match arg {
-                Value::Integer(i) => print!("{}", i),
-                Value::String(s) => print!("{}", s),
-                Value::Boolean(b) => print!("{}", b),
-                _ => return Err("Unsupported type for print".to_string()),
-            }
+            print!("{}", arg);
         }
         let _ = stdout().flush();
         let mut s = String::new();
-        stdin().read_line(&mut s).expect("Did not enter a correct string");
+        if let Err(error) = stdin().read_line(&mut s) {
+            return Err(Unwind::error(ErrorKind::IoError, format!("Could not read input: {}", error)));
+        }
         Ok(Value::String(s.trim().to_string()))
     }
-    fn str(args: Vec<Value>)  -> Result<Value, String> {
+    fn exit(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::Integer(code)] if !(0..=255).contains(code) => Err(Unwind::error(
+                ErrorKind::ValueError,
+                format!("exit code {} is outside 0 to 255", code),
+            )),
+            [Value::Integer(code)] => {
+                evaluator.exit_status = Some(*code);
+                Err(Unwind::Exit(*code))
+            }
+            _ => Err(bad_arguments(&args, 1, "exit() takes exactly one integer exit code")),
+        }
+    }
+    fn str(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
         let mut retval = String::new();
         for arg in args {
-            match arg {
-                Value::Integer(i) => retval.push_str(&format!("{}", i)),
-                Value::String(s) => retval.push_str(&format!("{}", s)),
-                Value::Boolean(b) => retval.push_str(&format!("{}", b)),
-                _ => return Err("Unsupported type for print".to_string()),
-            }
+            retval.push_str(&arg.to_string());
         }
         Ok(Value::String(retval))
     }
-    fn int(args: Vec<Value>) -> Result<Value, String> {
-        let input = Builtins::str(args).expect("Failed to parse int() arguments"); // Use builtin formatter to convert args to str
-        if let Value::String(s) = input {
-            let retval: i32 = s.parse().expect("Failed to convert int() inputs to signed integer value");
-            Ok(Value::Integer(retval))
+    fn int(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match &mut Builtins::try_int(evaluator, args)? {
+            Value::Ok(value) => Ok(value.take()),
+            Value::Err(error) => Err(Unwind::Throw(error.take())),
+            other => Err(Unwind::error(ErrorKind::ValueError, format!("int() failed: {}", other))),
+        }
+    }
+    fn try_int(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        let input = Builtins::str(evaluator, args)?; // Use builtin formatter to convert args to str
+        if let Value::String(s) = &input {
+            match s.parse() {
+                Ok(retval) => Ok(Value::Ok(Box::new(Value::Integer(retval)))),
+                Err(_) => Ok(Value::Err(Box::new(Value::Error(Box::new(RuntimeError::new(
+                    ErrorKind::ValueError,
+                    format!("Cannot convert {:?} to an integer", s),
+                )))))),
+            }
         } else {
-            Err("int() input is not a string.".to_string())
+            Err(Unwind::error(ErrorKind::InternalError, "str() did not return a string"))
+        }
+    }
+    fn is_none(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [value] => Ok(Value::Boolean(value.is_nil())),
+            _ => Err(bad_arguments(&args, 1, "is_none() takes exactly one argument")),
+        }
+    }
+    fn error(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::String(kind), message] => Ok(Value::Error(Box::new(RuntimeError::new(
+                ErrorKind::from_name(kind),
+                message.to_string(),
+            )))),
+            _ => Err(bad_arguments(&args, 2, "error() takes a kind string and a message")),
+        }
+    }
+    fn error_kind(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::Error(error)] => Ok(Value::String(error.kind.to_string())),
+            _ => Err(bad_arguments(&args, 1, "error_kind() takes exactly one error")),
+        }
+    }
+    fn error_message(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::Error(error)] => Ok(Value::String(error.message.clone())),
+            _ => Err(bad_arguments(&args, 1, "error_message() takes exactly one error")),
         }
     }
+    fn ok(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [value] => Ok(Value::Ok(Box::new(value.clone()))),
+            _ => Err(bad_arguments(&args, 1, "Ok() takes exactly one argument")),
+        }
+    }
+    fn err(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [value] => Ok(Value::Err(Box::new(value.clone()))),
+            _ => Err(bad_arguments(&args, 1, "Err() takes exactly one argument")),
+        }
+    }
+    fn is_ok(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [value] => Ok(Value::Boolean(matches!(value, Value::Ok(_)))),
+            _ => Err(bad_arguments(&args, 1, "is_ok() takes exactly one argument")),
+        }
+    }
+    fn is_err(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [value] => Ok(Value::Boolean(matches!(value, Value::Err(_)))),
+            _ => Err(bad_arguments(&args, 1, "is_err() takes exactly one argument")),
+        }
+    }
+    fn unwrap(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::Ok(value)] => Ok(*value.clone()),
+            [err @ Value::Err(_)] => Err(Unwind::error(ErrorKind::ValueError, format!("called unwrap() on {}", err))),
+            _ => Err(bad_arguments(&args, 1, "unwrap() takes exactly one Ok or Err value")),
+        }
+    }
+    fn len(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        let len = match args.as_slice() {
+            [Value::List(items)] => items.borrow().len(),
+            [Value::Map(entries)] => entries.borrow().len(),
+            [Value::String(s)] => s.chars().count(),
+            _ => return Err(bad_arguments(&args, 1, "len() takes exactly one list, map or string")),
+        };
+        i32::try_from(len)
+            .map(Value::Integer)
+            .map_err(|_| Unwind::error(ErrorKind::Overflow, format!("Length {} does not fit in an integer", len)))
+    }
+    fn push(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::List(items), value] => {
+                items.borrow_mut().push(value.clone());
+                Ok(Value::Nil)
+            }
+            _ => Err(bad_arguments(&args, 2, "push() takes a list and a value")),
+        }
+    }
+    fn dict(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        if !args.len().is_multiple_of(2) {
+            return Err(Unwind::error(ErrorKind::ArityMismatch, "dict() takes alternating keys and values"));
+        }
+        let entries = RefCell::new(vec![]);
+        for pair in args.chunks_exact(2) {
+            if let [key, value] = pair {
+                Value::map_insert(&entries, key.clone(), value.clone());
+            }
+        }
+        Ok(Value::new_map(entries.into_inner()))
+    }
+    fn insert(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [Value::Map(entries), key, value] => {
+                Value::map_insert(entries, key.clone(), value.clone());
+                Ok(Value::Nil)
+            }
+            _ => Err(bad_arguments(&args, 3, "insert() takes a map, a key and a value")),
+        }
+    }
+    fn iter(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => Ok(Value::Iterator(Iter::from_value(iterable)?)),
+            _ => Err(bad_arguments(&args, 1, "iter() takes exactly one argument")),
+        }
+    }
+    /// `Ok(item)`, or an `Err` holding a `StopIteration` error once the
+    /// iterator is exhausted, so a `None` item is told apart from the end.
+    fn next(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => {
+                let iter = Iter::from_value(iterable)?;
+                Ok(match evaluator.next_item(&iter)? {
+                    Some(item) => Value::Ok(Box::new(item)),
+                    None => Value::Err(Box::new(Value::Error(Box::new(RuntimeError::new(
+                        ErrorKind::Custom("StopIteration".to_string()),
+                        "iterator is exhausted",
+                    ))))),
+                })
+            }
+            _ => Err(bad_arguments(&args, 1, "next() takes exactly one argument")),
+        }
+    }
+    fn enumerate(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => Ok(Iter::Enumerate {
+                inner: Iter::from_value(iterable)?,
+                index: 0,
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 1, "enumerate() takes exactly one argument")),
+        }
+    }
+    fn map(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable, func] => Ok(Iter::Map {
+                inner: Iter::from_value(iterable)?,
+                func: func.clone(),
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "map() takes an iterable and a function")),
+        }
+    }
+    fn filter(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable, func] => Ok(Iter::Filter {
+                inner: Iter::from_value(iterable)?,
+                func: func.clone(),
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "filter() takes an iterable and a function")),
+        }
+    }
+    fn take(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable, Value::Integer(n)] => Ok(Iter::Take {
+                inner: Iter::from_value(iterable)?,
+                remaining: (*n).max(0),
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "take() takes an iterable and an integer")),
+        }
+    }
+    fn skip(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable, Value::Integer(n)] => Ok(Iter::Skip {
+                inner: Iter::from_value(iterable)?,
+                remaining: (*n).max(0),
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "skip() takes an iterable and an integer")),
+        }
+    }
+    fn zip(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [left, right] => Ok(Iter::Zip {
+                left: Iter::from_value(left)?,
+                right: Iter::from_value(right)?,
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "zip() takes exactly two iterables")),
+        }
+    }
+    fn chain(_: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [first, second] => Ok(Iter::Chain {
+                first: Iter::from_value(first)?,
+                second: Iter::from_value(second)?,
+            }
+            .into_value()),
+            _ => Err(bad_arguments(&args, 2, "chain() takes exactly two iterables")),
+        }
+    }
+    fn collect(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => {
+                let iter = Iter::from_value(iterable)?;
+                let mut items = vec![];
+                while let Some(item) = evaluator.next_item(&iter)? {
+                    items.push(item);
+                }
+                Ok(Value::new_list(items))
+            }
+            _ => Err(bad_arguments(&args, 1, "collect() takes exactly one argument")),
+        }
+    }
+    fn sum(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => {
+                let iter = Iter::from_value(iterable)?;
+                let mut total: i32 = 0;
+                while let Some(item) = evaluator.next_item(&iter)? {
+                    match item {
+                        Value::Integer(i) => {
+                            total = total.checked_add(i).ok_or_else(|| {
+                                Unwind::error(ErrorKind::Overflow, "Integer overflow in sum()")
+                            })?
+                        }
+                        other => return Err(Unwind::error(ErrorKind::TypeMismatch, format!("sum() cannot add {}", other))),
+                    }
+                }
+                Ok(Value::Integer(total))
+            }
+            _ => Err(bad_arguments(&args, 1, "sum() takes exactly one argument")),
+        }
+    }
+    fn count(evaluator: &mut Evaluator, args: Vec<Value>) -> Result<Value, Unwind> {
+        match args.as_slice() {
+            [iterable] => {
+                let iter = Iter::from_value(iterable)?;
+                let mut count: i32 = 0;
+                while evaluator.next_item(&iter)?.is_some() {
+                    count = count
+                        .checked_add(1)
+                        .ok_or_else(|| Unwind::error(ErrorKind::Overflow, "Integer overflow in count()"))?;
+                }
+                Ok(Value::Integer(count))
+            }
+            _ => Err(bad_arguments(&args, 1, "count() takes exactly one argument")),
+        }
+    }
+}
+
+/// The error for arguments matching none of a builtin's signatures: an
+/// `ArityMismatch` when there are not `arity` of them, else a `TypeMismatch`.
+fn bad_arguments(args: &[Value], arity: usize, message: &str) -> Unwind {
+    let kind = if args.len() == arity {
+        ErrorKind::TypeMismatch
+    } else {
+        ErrorKind::ArityMismatch
+    };
+    Unwind::error(kind, message)
 }
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{AstNode, Parameter, Span};
+
+/// Words the grammar gives meaning to, which therefore cannot name functions,
+/// parameters or variables. `in` and `step` only mean something inside a
+/// for-in or a range, so they are left free.
+pub const RESERVED_WORDS: &[&str] = &[
+    "fn", "let", "return", "yield", "throw", "try", "catch", "finally", "for", "while", "if", "else",
+    "True", "False", "None",
+];
+
+/// The rule a `CheckError` is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckErrorKind {
+    DuplicateFunction,
+    DuplicateParameter,
+    ReservedWord,
+    /// A `...rest` parameter that is not the last one.
+    MisplacedRest,
+    /// A parameter without a default after one with a default.
+    MissingDefault,
+    /// A positional argument after a `name: value` one.
+    PositionalAfterNamed,
+    /// A `yield` in the body, handler or `finally` of a `try`.
+    YieldInTry,
+    /// A `yield` inside an expression, or outside any function.
+    MisplacedYield,
+}
+
+/// A problem found by `check` in a program that parsed successfully.
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    pub kind: CheckErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Related places in the source, each with a short explanation.
+    pub labels: Vec<(Span, String)>,
+}
+
+impl CheckError {
+    /// The stable code `firstlang explain` knows this error by.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            CheckErrorKind::DuplicateFunction => "E0007",
+            CheckErrorKind::DuplicateParameter => "E0008",
+            CheckErrorKind::ReservedWord => "E0009",
+            CheckErrorKind::MisplacedRest => "E0010",
+            CheckErrorKind::MissingDefault => "E0011",
+            CheckErrorKind::PositionalAfterNamed => "E0027",
+            CheckErrorKind::YieldInTry => "E0028",
+            CheckErrorKind::MisplacedYield => "E0029",
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Semantic checks run between parsing and evaluation. Every problem in the
+/// program is reported, not just the first.
+pub fn check(program: &AstNode) -> Result<(), Vec<CheckError>> {
+    let mut checker = Checker { errors: vec![], span: None, in_try: false, statement: false };
+    checker.check_program(program);
+
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct Checker {
+    errors: Vec<CheckError>,
+    /// Span of the innermost `Spanned` node being checked.
+    span: Option<Span>,
+    /// Whether the node being checked is inside a `try`, where generators cannot pause.
+    in_try: bool,
+    /// Whether the node being checked is a statement a generator can pause on:
+    /// one in a function body, or in a block, `if` or loop that is one itself.
+    statement: bool,
+}
+
+impl Checker {
+    fn error(&mut self, kind: CheckErrorKind, message: String) {
+        self.errors.push(CheckError { kind, message, span: self.span, labels: vec![] });
+    }
+
+    fn check_program(&mut self, program: &AstNode) {
+        let mut functions = HashMap::new();
+        for node in program.children() {
+            self.span = node.span();
+            if let AstNode::FuncDef { name, .. } = node.unspanned() {
+                match functions.get(name.as_str()) {
+                    Some(first) => {
+                        self.error(CheckErrorKind::DuplicateFunction, format!("Function '{}' is defined more than once", name));
+                        if let (Some(error), Some(first)) = (self.errors.last_mut(), first) {
+                            error.labels.push((*first, "first defined here".to_string()));
+                        }
+                    }
+                    None => {
+                        functions.insert(name.as_str(), self.span);
+                    }
+                }
+            }
+            self.check_node(node);
+        }
+    }
+
+    fn check_name(&mut self, what: &str, name: &str) {
+        if RESERVED_WORDS.contains(&name) {
+            self.error(CheckErrorKind::ReservedWord, format!("'{}' is a reserved word and cannot be used as a {} name", name, what));
+        }
+    }
+
+    fn check_parameters(&mut self, function: &str, params: &[Parameter]) {
+        let mut seen = HashSet::new();
+        let mut seen_default = false;
+        for (i, param) in params.iter().enumerate() {
+            self.check_name("parameter", &param.name);
+            if !seen.insert(param.name.as_str()) {
+                self.error(CheckErrorKind::DuplicateParameter, format!("{}(): duplicate parameter '{}'", function, param.name));
+            }
+            if param.rest && i != params.len() - 1 {
+                self.error(CheckErrorKind::MisplacedRest, format!("{}(): rest parameter '...{}' must come last", function, param.name));
+            }
+            if param.default.is_some() {
+                seen_default = true;
+            } else if seen_default && !param.rest {
+                self.error(CheckErrorKind::MissingDefault, format!(
+                    "{}(): parameter '{}' without a default follows one with a default",
+                    function, param.name
+                ));
+            }
+        }
+    }
+
+    fn check_arguments(&mut self, function: &str, args: &[Box<AstNode>]) {
+        let mut named = None;
+        for arg in args {
+            match (arg.unspanned(), named) {
+                (AstNode::NamedArg { name, .. }, None) => named = Some(name),
+                (AstNode::NamedArg { .. }, Some(_)) => {}
+                (_, Some(name)) => {
+                    let outer = self.span;
+                    self.span = arg.span().or(outer);
+                    self.error(CheckErrorKind::PositionalAfterNamed, format!(
+                        "{}(): positional argument after the named argument '{}'",
+                        function, name
+                    ));
+                    self.span = outer;
+                }
+                (_, None) => {}
+            }
+        }
+    }
+
+    fn check_node(&mut self, node: &AstNode) {
+        let outer = self.span;
+        self.span = node.span().or(outer);
+        match node {
+            AstNode::FuncDef { name, args, .. } => {
+                self.check_name("function", name);
+                if let AstNode::DefArgList(params) = &**args {
+                    self.check_parameters(name, params);
+                }
+            }
+            AstNode::VarDecl { name, .. } => self.check_name("variable", name),
+            AstNode::FuncCall { name, args } => {
+                if let AstNode::ArgList(args) = &**args {
+                    self.check_arguments(name, args);
+                }
+            }
+            AstNode::ForInLoop { names, .. } => {
+                for name in names {
+                    self.check_name("variable", name);
+                }
+            }
+            AstNode::TryCatch { catch: Some((name, _)), .. } => self.check_name("variable", name),
+            AstNode::Yield(_) if self.in_try => {
+                self.error(CheckErrorKind::YieldInTry, "yield is not supported inside try blocks".to_string())
+            }
+            AstNode::Yield(_) if !self.statement => {
+                self.error(CheckErrorKind::MisplacedYield, "yield can only be used as a statement inside a function".to_string())
+            }
+            AstNode::VarSet { target, .. } => {
+                if let AstNode::Identifier(name) = &**target {
+                    self.check_name("variable", name);
+                }
+            }
+            _ => {}
+        }
+
+        let (outer_try, outer_statement) = (self.in_try, self.statement);
+        self.in_try |= matches!(node, AstNode::TryCatch { .. });
+        // The children from this index on are statements too; conditions,
+        // iterables and operands never are.
+        let statements_from = match node {
+            AstNode::FuncDef { .. } => Some(1),
+            _ if !outer_statement => None,
+            AstNode::Block { .. } | AstNode::TryCatch { .. } | AstNode::Spanned(..) => Some(0),
+            AstNode::Expression(_) | AstNode::Term(_) => {
+                matches!(node.unwrap_expression(), AstNode::Block { .. }).then_some(0)
+            }
+            AstNode::IfStatement { .. }
+            | AstNode::WhileLoop { .. }
+            | AstNode::ForLoop { .. }
+            | AstNode::ForInLoop { .. } => Some(1),
+            _ => None,
+        };
+        for (i, child) in node.children().into_iter().enumerate() {
+            self.statement = statements_from.is_some_and(|first| i >= first);
+            self.check_node(child);
+        }
+        (self.in_try, self.statement) = (outer_try, outer_statement);
+        self.span = outer;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn check_source(source: &str) -> Vec<String> {
+        match check(&parser::parse(source).unwrap()) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_programs() {
+        assert!(check_source("fn f(a , b = 1, ...rest) { let x = a; } fn main() { f(1); }").is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        assert_eq!(
+            check_source("fn f(a, a) { } fn f() { }"),
+            vec!["f(): duplicate parameter 'a'", "Function 'f' is defined more than once"]
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_words() {
+        assert_eq!(
+            check_source("fn main() { let None = 1; for x, while in [] { } }"),
+            vec![
+                "'None' is a reserved word and cannot be used as a variable name",
+                "'while' is a reserved word and cannot be used as a variable name",
+            ]
+        );
+        assert_eq!(
+            check_source("fn catch(try) { }"),
+            vec![
+                "'catch' is a reserved word and cannot be used as a function name",
+                "'try' is a reserved word and cannot be used as a parameter name",
+            ]
+        );
+        assert!(check_source("fn f(in) { let step = 2; for x in 0..in step step { } }").is_empty());
+    }
+
+    #[test]
+    fn rejects_misplaced_rest_and_defaults() {
+        assert_eq!(
+            check_source("fn f(...rest, a) { } fn g(a = 1, b) { }"),
+            vec![
+                "f(): rest parameter '...rest' must come last",
+                "g(): parameter 'b' without a default follows one with a default",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_positional_after_named_arguments() {
+        assert!(check_source("fn f(a, b = 1) { } fn main() { f(1, b: 2); f(b: 2, a: 1); }").is_empty());
+        assert_eq!(
+            check_source("fn f(a, b = 1) { } fn main() { f(b: 1, 2); }"),
+            vec!["f(): positional argument after the named argument 'b'"]
+        );
+        let errors = check(&parser::parse("fn main() {\n  f(a: 1,\n    2);\n}").unwrap()).unwrap_err();
+        assert_eq!(errors[0].code(), "E0027");
+        assert_eq!(errors[0].span.map(|span| (span.line, span.col)), Some((3, 5)));
+    }
+
+    #[test]
+    fn rejects_yield_inside_try() {
+        assert!(check_source("fn g() { let x = 0; try { x = 1; } catch e { } yield x; }").is_empty());
+        let source = "fn g() {\n  try { yield 1; } catch e { yield 2; } finally { if True { yield 3; } }\n}";
+        let errors = check(&parser::parse(source).unwrap()).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].code(), "E0028");
+        assert_eq!(errors[0].message, "yield is not supported inside try blocks");
+        assert_eq!(errors[0].span.map(|span| (span.line, span.col)), Some((2, 9)));
+    }
+
+    #[test]
+    fn rejects_yield_inside_expressions() {
+        let source = "fn g(xs) {
+            yield 0;
+            { if True { yield 1; } else if False { for x in xs { while True { yield x; } } } }
+            ({ yield 2; });
+        }";
+        assert!(check_source(source).is_empty());
+        let source = "fn g() { let x = { yield 1; 2 }; print(if True { yield 3; }); yield { yield 4; }; }";
+        assert_eq!(check_source(source), vec!["yield can only be used as a statement inside a function"; 3]);
+        let errors = check(&parser::parse_repl_input("yield 1;").unwrap()).unwrap_err();
+        assert_eq!(errors[0].code(), "E0029");
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_node() {
+        let errors = check(&parser::parse("fn main() {\n  let None = 1;\n}").unwrap()).unwrap_err();
+        assert_eq!(errors[0].span.map(|span| (span.line, span.col)), Some((2, 3)));
+    }
+}
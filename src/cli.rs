@@ -0,0 +1,195 @@
+use crate::interpreter::DEFAULT_MAX_DEPTH;
+
+pub const USAGE: &str = "\
+Usage: firstlang [command] [options] [file] [args...]
+
+Commands:
+  run <file>       run a program (the default when only a file is given);
+                   arguments after the file are passed to `fn main(args)`
+  check <file>     report syntax and semantic errors without running
+  fmt <file>       format a program in place; from stdin, print the result
+  ast <file>       print the syntax tree
+  test <file>      run every function whose name starts with `test_`
+  repl             start an interactive session (the default without a file)
+  explain <code>   describe an error code such as E0012
+  help             show this message
+
+Options:
+  --entry <fn>       function to start at instead of main
+  --max-depth <n>    maximum depth of nested calls (default 1000)
+  --no-color         never color error reports
+  --check            with fmt, only report whether the file is formatted
+  -h, --help         show this message
+
+A file of `-` reads the program from standard input.
+
+Exit status: 0 on success, or the integer main returns or passes to exit();
+1 for a runtime error, a failed test or a file fmt --check would change,
+2 for bad arguments, 3 for syntax and semantic errors, 4 if the file cannot
+be read. A program may return or exit with 1 to 4 itself, so a script that
+needs to tell its own statuses from these should use other values.";
+
+/// Process exit statuses, so scripts can tell failures apart. A program's own
+/// status, from `main` or `exit()`, may be any of them too; `USAGE` says so.
+pub const EXIT_SUCCESS: i32 = 0;
+/// A runtime error, a failing test, or a file `fmt --check` would change.
+pub const EXIT_RUNTIME_ERROR: i32 = 1;
+/// Bad command-line arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// A syntax or semantic error; nothing was run.
+pub const EXIT_SYNTAX_ERROR: i32 = 3;
+/// The source could not be read.
+pub const EXIT_IO_ERROR: i32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Check,
+    Fmt,
+    Ast,
+    Test,
+    Repl,
+    Explain(String),
+    Help,
+}
+
+/// Everything the command line asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub command: Command,
+    /// The source file, `-` for standard input.
+    pub path: Option<String>,
+    pub entry: String,
+    pub max_depth: usize,
+    pub no_color: bool,
+    /// `fmt --check`: report unformatted files instead of rewriting them.
+    pub check: bool,
+    /// Arguments after the file, for the program's `main`.
+    pub args: Vec<String>,
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        path: None,
+        entry: "main".to_string(),
+        max_depth: DEFAULT_MAX_DEPTH,
+        no_color: false,
+        check: false,
+        args: vec![],
+    };
+    let mut command = None;
+    let mut positional = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Everything after the file to run belongs to the program.
+        if !positional.is_empty() && matches!(command.as_deref(), None | Some("run")) {
+            options.args.push(arg);
+            options.args.extend(args.by_ref());
+            break;
+        }
+        match arg.as_str() {
+            "-h" | "--help" => command = Some("help".to_string()),
+            "--no-color" => options.no_color = true,
+            "--check" => options.check = true,
+            "--entry" => options.entry = args.next().ok_or("--entry expects a function name")?,
+            "--max-depth" => {
+                options.max_depth = args
+                    .next()
+                    .and_then(|depth| depth.parse().ok())
+                    .filter(|&depth| depth > 0)
+                    .ok_or("--max-depth expects a positive integer")?;
+            }
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("Unknown option {}", flag)),
+            "run" | "check" | "fmt" | "ast" | "test" | "repl" | "explain" | "help" if command.is_none() && positional.is_empty() => {
+                command = Some(arg)
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.path = positional.next();
+    options.command = match command.as_deref() {
+        Some("help") => Command::Help,
+        Some("explain") => Command::Explain(options.path.take().ok_or("explain expects an error code, e.g. E0012")?),
+        Some("repl") => Command::Repl,
+        None if options.path.is_none() => Command::Repl,
+        Some("check") => Command::Check,
+        Some("fmt") => Command::Fmt,
+        Some("ast") => Command::Ast,
+        Some("test") => Command::Test,
+        _ => Command::Run,
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument {}", extra));
+    }
+    if options.check && options.command != Command::Fmt {
+        return Err("--check only applies to fmt".to_string());
+    }
+    if let (Some(path), Command::Repl | Command::Help) = (&options.path, &options.command) {
+        let command = if options.command == Command::Repl { "repl" } else { "help" };
+        return Err(format!("{} does not take a file, but got {}", command, path));
+    }
+    if options.path.is_none() && !matches!(options.command, Command::Repl | Command::Help | Command::Explain(_)) {
+        return Err("No input file was provided".to_string());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_args(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn picks_commands_and_defaults() {
+        let options = parse("fib.cheese").unwrap();
+        assert_eq!((options.command, options.path.as_deref()), (Command::Run, Some("fib.cheese")));
+        assert_eq!(parse("").unwrap().command, Command::Repl);
+        assert_eq!(parse("check -").unwrap().path.as_deref(), Some("-"));
+        assert_eq!(parse("ast f.cheese").unwrap().command, Command::Ast);
+        assert_eq!(parse("explain E0012").unwrap().command, Command::Explain("E0012".to_string()));
+        assert_eq!(parse("--help").unwrap().command, Command::Help);
+        assert_eq!(parse("help").unwrap().command, Command::Help);
+        // A file may share a command's name.
+        assert_eq!(parse("run test").unwrap().path.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn reads_options() {
+        let options = parse("test --no-color --entry start --max-depth 50 t.cheese").unwrap();
+        assert_eq!(options.command, Command::Test);
+        assert_eq!(options.entry, "start");
+        assert_eq!(options.max_depth, 50);
+        assert!(options.no_color);
+        assert!(parse("fmt --check f.cheese").unwrap().check);
+    }
+
+    #[test]
+    fn passes_arguments_after_the_file_to_the_program() {
+        let options = parse("--no-color tool.cheese a --max-depth 3").unwrap();
+        assert_eq!(options.path.as_deref(), Some("tool.cheese"));
+        assert_eq!(options.args, ["a", "--max-depth", "3"]);
+        assert_eq!(options.max_depth, DEFAULT_MAX_DEPTH);
+        assert_eq!(parse("run - x").unwrap().args, ["x"]);
+        assert_eq!(parse("check f.cheese x").unwrap_err(), "Unexpected argument x");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(parse("run").unwrap_err(), "No input file was provided");
+        assert_eq!(parse("--max-depth x f").unwrap_err(), "--max-depth expects a positive integer");
+        assert_eq!(parse("--max-depth 0 f").unwrap_err(), "--max-depth expects a positive integer");
+        assert_eq!(parse("--colour f").unwrap_err(), "Unknown option --colour");
+        assert_eq!(parse("-v f.cheese").unwrap_err(), "Unknown option -v");
+        assert_eq!(parse("explain").unwrap_err(), "explain expects an error code, e.g. E0012");
+        assert_eq!(parse("repl f.cheese").unwrap_err(), "repl does not take a file, but got f.cheese");
+        assert_eq!(parse("--help foo.cheese").unwrap_err(), "help does not take a file, but got foo.cheese");
+        assert_eq!(parse("--check f.cheese").unwrap_err(), "--check only applies to fmt");
+    }
+}
@@ -0,0 +1,394 @@
+/// The long-form description behind an error code, as printed by
+/// `firstlang explain`.
+#[derive(Debug)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// A program that fails with this error, and the same program fixed.
+    /// `None` for errors no program can reliably cause.
+    pub example: Option<(&'static str, &'static str)>,
+}
+
+/// Every code the parser, checker and interpreter report. Codes are never
+/// reused or renumbered; retired errors keep their entry.
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "missing semicolon",
+        description: "\
+A statement ran into the next one without a `;` between them. Every
+statement except a block, `if`, loop or `try` must end with a semicolon.",
+        example: Some((
+            "fn main() {\n    let x = 1\n    print(x);\n}",
+            "fn main() {\n    let x = 1;\n    print(x);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "missing separator",
+        description: "\
+Two arguments of a call, or two items of a list, are not separated by a
+comma. Each item is followed either by `,` or by the closing bracket.",
+        example: Some(("fn main() {\n    print(1 2);\n}", "fn main() {\n    print(1, 2);\n}")),
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "unclosed delimiter",
+        description: "\
+The file ended while a `(`, `[` or `{` was still open. The error points at
+the end of the file; the bracket that is missing its partner is usually
+the last one opened.",
+        example: Some(("fn main() {\n    print(1);\n", "fn main() {\n    print(1);\n}")),
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "unexpected token",
+        description: "\
+The parser found a token that cannot appear at this point. The message
+lists what would have been accepted instead.",
+        example: Some(("fn main() {\n    let x = * 2;\n}", "fn main() {\n    let x = 3 * 2;\n}")),
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "integer literal too large",
+        description: "\
+Integers are 32-bit and signed, so a literal must be at most 2147483647.
+Negative numbers are written with unary minus, which applies to the
+literal after it has been read.",
+        example: Some((
+            "fn main() {\n    return 3000000000;\n}",
+            "fn main() {\n    print(2147483647);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "internal parser error",
+        description: "\
+The grammar accepted a construct the parser does not know how to turn into
+a syntax tree. This is a bug in firstlang, not in your program; please
+report it along with the source that caused it.",
+        example: None,
+    },
+    ErrorCode {
+        code: "E0007",
+        title: "duplicate function",
+        description: "\
+Two functions share a name. Functions live in one global namespace, so
+each name can be defined once; rename one of them.",
+        example: Some((
+            "fn greet() { print(\"hi\"); }\nfn greet() { print(\"hello\"); }\nfn main() { greet(); }",
+            "fn greet() { print(\"hi\"); }\nfn greet_warmly() { print(\"hello\"); }\nfn main() { greet(); }",
+        )),
+    },
+    ErrorCode {
+        code: "E0008",
+        title: "duplicate parameter",
+        description: "\
+A function lists the same parameter name twice, so the second would hide
+the first.",
+        example: Some((
+            "fn add(a, a) { return a + a; }\nfn main() { add(1, 2); }",
+            "fn add(a, b) { return a + b; }\nfn main() { add(1, 2); }",
+        )),
+    },
+    ErrorCode {
+        code: "E0009",
+        title: "reserved word used as a name",
+        description: "\
+Keywords such as `fn`, `let`, `while`, `True` and `None` have a meaning
+in the grammar and cannot name a function, parameter or variable. `in` and
+`step` are only keywords inside a for-in loop or a range, so they can.",
+        example: Some((
+            "fn main() {\n    let while = 2;\n    print(while);\n}",
+            "fn main() {\n    let times = 2;\n    print(times);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0010",
+        title: "rest parameter not last",
+        description: "\
+A `...rest` parameter collects every remaining positional argument, so no
+parameter can come after it.",
+        example: Some((
+            "fn log(...parts, level) { print(level, parts); }\nfn main() { log(1, 2); }",
+            "fn log(level, ...parts) { print(level, parts); }\nfn main() { log(1, 2); }",
+        )),
+    },
+    ErrorCode {
+        code: "E0011",
+        title: "parameter without default after one with a default",
+        description: "\
+Positional arguments fill parameters from the left, so once a parameter
+has a default every later one needs a default too. Otherwise it could only
+ever be passed by name.",
+        example: Some((
+            "fn range_of(start = 0, end) { return end - start; }\nfn main() { range_of(1, 2); }",
+            "fn range_of(end, start = 0) { return end - start; }\nfn main() { range_of(2, 1); }",
+        )),
+    },
+    ErrorCode {
+        code: "E0012",
+        title: "undefined variable",
+        description: "\
+A name was read before a `let` or an assignment gave it a value. Blocks
+do not start a scope of their own: a variable belongs to its whole
+function from the moment it is first set, so one set only in an `if` that
+did not run is still undefined. Functions cannot see the variables of
+their callers.",
+        example: Some((
+            "fn main() {\n    print(total);\n}",
+            "fn main() {\n    let total = 0;\n    print(total);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0013",
+        title: "undefined function",
+        description: "\
+A call names neither a builtin nor a function defined with `fn` at the top
+level of the file, nor a variable holding a function.",
+        example: Some((
+            "fn main() {\n    shout(\"hi\");\n}",
+            "fn shout(text) { print(text, \"!\"); }\nfn main() {\n    shout(\"hi\");\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0014",
+        title: "type mismatch",
+        description: "\
+An operator or builtin received a value of a type it cannot handle, such
+as adding a boolean to an integer. Convert the value first, for example
+with `str()` or `int()`.",
+        example: Some(("fn main() {\n    return 1 + True;\n}", "fn main() {\n    return 1 + 1;\n}")),
+    },
+    ErrorCode {
+        code: "E0015",
+        title: "wrong number of arguments",
+        description: "\
+A call passed more or fewer arguments than the function accepts, or named
+a parameter that does not exist.",
+        example: Some((
+            "fn add(a, b) { return a + b; }\nfn main() {\n    return add(1);\n}",
+            "fn add(a, b) { return a + b; }\nfn main() {\n    return add(1, 2);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0016",
+        title: "division by zero",
+        description: "\
+The right-hand side of `/` or `%` was zero. Check the divisor first, or
+catch the error with `try`.",
+        example: Some((
+            "fn main() {\n    let n = 0;\n    return 10 / n;\n}",
+            "fn main() {\n    let n = 0;\n    return n == 0 ? 0 : 10 / n;\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0017",
+        title: "integer overflow",
+        description: "\
+An arithmetic result does not fit in a 32-bit signed integer, whose range
+is -2147483648 to 2147483647. Results are never silently wrapped.",
+        example: Some((
+            "fn main() {\n    print(2147483647 + 1);\n}",
+            "fn main() {\n    print(2147483646 + 1);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0018",
+        title: "index out of range",
+        description: "\
+A list or string was indexed past its end, or with a negative number.
+Valid indexes run from 0 to `len(x) - 1`.",
+        example: Some((
+            "fn main() {\n    let xs = [1, 2];\n    return xs[2];\n}",
+            "fn main() {\n    let xs = [1, 2];\n    return xs[len(xs) - 1];\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0019",
+        title: "invalid value",
+        description: "\
+A value had the right type but unusable contents, such as `int()` of a
+string that is not a number or a range with a step of zero. `try_int()`
+returns an `Err` instead, which the program can check for.",
+        example: Some((
+            "fn main() {\n    return int(\"12x\");\n}",
+            concat!(
+                "fn main() {\n    let n = try_int(\"12x\");\n",
+                "    if is_ok(n) {\n        print(unwrap(n));\n    } else {\n        print(\"not a number\");\n    }\n}",
+            ),
+        )),
+    },
+    ErrorCode {
+        code: "E0020",
+        title: "recursion too deep",
+        description: "\
+Calls nested deeper than the limit, 1000 by default. Raise it with
+`--max-depth`, make sure the recursion has a base case, or turn the
+recursive call into a tail call with `return f(...)`, which does not grow
+the stack.",
+        example: Some((
+            "fn count(n) { return 1 + count(n - 1); }\nfn main() {\n    return count(5);\n}",
+            "fn count(n) { if n == 0 { return 0; } return 1 + count(n - 1); }\nfn main() {\n    return count(5);\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0021",
+        title: "input error",
+        description: "\
+`input()` could not read a line from standard input, for example because
+it is not valid UTF-8.",
+        example: None,
+    },
+    ErrorCode {
+        code: "E0022",
+        title: "unchecked syntax",
+        description: "\
+A program that was never checked used a construct the checker rejects: a
+positional argument after a named one, or a `yield` inside a `try` or an
+expression. `firstlang` checks every program before running it, so it
+reports E0027, E0028 or E0029 for these instead.",
+        example: None,
+    },
+    ErrorCode {
+        code: "E0023",
+        title: "invalid main function",
+        description: "\
+Programs start at `main` (or the function given with `--entry`), which
+must be defined with `fn` at the top level and must not yield. It takes no
+parameters, or one that receives the command-line arguments as a list of
+strings; `...args` receives them the same way.",
+        example: Some(("fn start() {\n    print(1);\n}", "fn main() {\n    print(1);\n}")),
+    },
+    ErrorCode {
+        code: "E0024",
+        title: "uncaught throw",
+        description: "\
+A value that is not an error was thrown and nothing caught it. Catch it
+with `try`, or throw `error(kind, message)` so the report says what went
+wrong.",
+        example: Some((
+            "fn main() {\n    throw \"oops\";\n}",
+            "fn main() {\n    try { throw \"oops\"; } catch e { print(e); }\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0025",
+        title: "internal interpreter error",
+        description: "\
+The interpreter reached a state it should never be in. This is a bug in
+firstlang, not in your program; please report it along with the source
+that caused it.",
+        example: None,
+    },
+    ErrorCode {
+        code: "E0026",
+        title: "code nested too deeply",
+        description: "\
+Brackets, `?:` ternaries or `else if` chains are nested more than 200
+levels deep, or an expression chains so many operators that its syntax tree
+is too deep to walk. Move inner parts into variables or functions of their
+own.",
+        example: None,
+    },
+    ErrorCode {
+        code: "E0027",
+        title: "positional argument after a named one",
+        description: "\
+Positional arguments fill parameters from the left, so they must all come
+before any `name: value` argument in a call.",
+        example: Some((
+            "fn area(w, h = 1) { return w * h; }\nfn main() { print(area(h: 2, 3)); }",
+            "fn area(w, h = 1) { return w * h; }\nfn main() { print(area(3, h: 2)); }",
+        )),
+    },
+    ErrorCode {
+        code: "E0028",
+        title: "yield inside try",
+        description: "\
+A generator cannot pause inside the body, handler or `finally` of a `try`,
+because the `try` would have to stay open across resumptions. Yield the
+value after the `try` instead.",
+        example: Some((
+            "fn g() { try { yield 1; } catch e { } }\nfn main() {\n    return sum(g());\n}",
+            "fn g() { let x = 0; try { x = 1; } catch e { } yield x; }\nfn main() {\n    return sum(g());\n}",
+        )),
+    },
+    ErrorCode {
+        code: "E0029",
+        title: "yield inside an expression",
+        description: "\
+`yield` pauses a generator between statements, so it must stand as a
+statement of the function body, or of a block, `if` or loop that is one
+itself. A block used as a value, such as the initial value of a `let`,
+cannot contain it.",
+        example: Some((
+            "fn g() {\n    let x = { yield 1; 2 };\n}\nfn main() {\n    print(sum(g()));\n}",
+            "fn g() {\n    yield 1;\n    let x = 2;\n}\nfn main() {\n    print(sum(g()));\n}",
+        )),
+    },
+];
+
+/// The registry entry for `code`, ignoring case, so `e0012` finds `E0012`.
+pub fn lookup(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES.iter().find(|entry| entry.code.eq_ignore_ascii_case(code))
+}
+
+impl ErrorCode {
+    /// The text `firstlang explain` prints.
+    pub fn explain(&self) -> String {
+        let indent = |source: &str| source.lines().map(|line| format!("    {line}\n")).collect::<String>();
+        let mut out = format!("{}: {}\n\n{}\n", self.code, self.title, self.description);
+        if let Some((wrong, fixed)) = self.example {
+            out.push_str(&format!("\nErroneous code example:\n\n{}\nCorrected:\n\n{}", indent(wrong), indent(fixed)));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Evaluator;
+    use crate::{checker, parser};
+
+    /// The code of the first error `source` fails with, if any.
+    fn first_error(source: &str) -> Option<&'static str> {
+        let ast = match parser::parse(source) {
+            Ok(ast) => ast,
+            Err(errors) => return Some(errors[0].code()),
+        };
+        if let Err(errors) = checker::check(&ast) {
+            return Some(errors[0].code());
+        }
+        let error = Evaluator::new().with_max_depth(10).run(&ast).err()?;
+        error.kind.code()
+    }
+
+    #[test]
+    fn codes_are_unique_and_ordered() {
+        for (i, entry) in ERROR_CODES.iter().enumerate() {
+            assert_eq!(entry.code, format!("E{:04}", i + 1));
+        }
+        assert_eq!(lookup("e0012").map(|entry| entry.title), Some("undefined variable"));
+        assert!(lookup("E9999").is_none());
+    }
+
+    #[test]
+    fn examples_fail_with_their_code_and_corrections_run() {
+        for entry in ERROR_CODES {
+            let Some((wrong, fixed)) = entry.example else { continue };
+            assert_eq!(first_error(wrong), Some(entry.code), "wrong example of {}", entry.code);
+            assert_eq!(first_error(fixed), None, "corrected example of {}", entry.code);
+        }
+    }
+
+    #[test]
+    fn explains_with_examples() {
+        let text = lookup("E0012").map(ErrorCode::explain).unwrap_or_default();
+        assert!(text.starts_with("E0012: undefined variable\n\nA name was read"));
+        assert!(text.contains("Erroneous code example:\n\n    fn main() {\n        print(total);\n    }\n"));
+        assert!(text.contains("Corrected:\n\n    fn main() {\n        let total = 0;\n"));
+    }
+}
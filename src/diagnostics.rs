@@ -0,0 +1,236 @@
+use std::fmt::Write;
+
+use crate::ast::Span;
+use crate::checker::CheckError;
+use crate::interpreter::{ErrorKind, RuntimeError};
+use crate::parser::ParseError;
+
+/// An error report in the style of a compiler message: a headline, labelled
+/// regions of the source and trailing help notes.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The error code shown as `error[E0012]`, if the error has one.
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// The span the error is about, underlined with `^`.
+    pub primary: Option<Label>,
+    /// Related spans, underlined with `-`.
+    pub secondary: Vec<Label>,
+    pub help: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+const RED: &str = "1;31";
+const BLUE: &str = "1;34";
+const CYAN: &str = "1;36";
+const BOLD: &str = "1";
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic {
+            code: None,
+            message: message.into(),
+            primary: None,
+            secondary: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn with_code(mut self, code: Option<&'static str>) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.primary = Some(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the report against `source`, the contents of `file`. With `color`
+    /// set the output contains ANSI escapes for a terminal.
+    pub fn render(&self, file: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| match color {
+            true => format!("\x1b[{style}m{text}\x1b[0m"),
+            false => text.to_string(),
+        };
+
+        let mut labels: Vec<(&Label, bool)> = self.primary.iter().map(|label| (label, true)).collect();
+        labels.extend(self.secondary.iter().map(|label| (label, false)));
+        labels.sort_by_key(|(label, _)| (label.span.line, label.span.col));
+
+        // Each line with the byte offset it starts at.
+        let mut lines = vec![];
+        let mut offset = 0;
+        for line in source.split('\n') {
+            lines.push((offset, line.trim_end_matches('\r')));
+            offset += line.len() + 1;
+        }
+        let width = labels.iter().map(|(label, _)| label.span.line.to_string().len()).max().unwrap_or(0);
+        let gutter = paint(BLUE, &format!("{:width$} |", ""));
+
+        let headline = match self.code {
+            Some(code) => format!("error[{code}]"),
+            None => "error".to_string(),
+        };
+        let mut out = format!("{}{}\n", paint(RED, &headline), paint(BOLD, &format!(": {}", self.message)));
+        if let Some(primary) = self.primary.as_ref().or(labels.first().map(|(label, _)| *label)) {
+            let arrow = paint(BLUE, &format!("{:width$}-->", ""));
+            let _ = writeln!(out, "{arrow} {}:{}:{}", file, primary.span.line, primary.span.col);
+        }
+
+        let mut previous_line = None;
+        for (label, is_primary) in &labels {
+            let line = label.span.line;
+            let Some(&(line_start, text)) = lines.get(line.saturating_sub(1)) else { continue };
+            if previous_line != Some(line) {
+                match previous_line {
+                    None => { let _ = writeln!(out, "{gutter}"); },
+                    Some(previous) if line > previous + 1 => { let _ = writeln!(out, "{}", paint(BLUE, "...")); },
+                    Some(_) => {},
+                }
+                let number = paint(BLUE, &format!("{line:width$} |"));
+                let _ = writeln!(out, "{number} {text}");
+                previous_line = Some(line);
+            }
+
+            // Underline up to the end of the label's first line, keeping tabs so
+            // the marks line up with the source above.
+            let column = label.span.col.saturating_sub(1).min(text.chars().count());
+            let indent: String = text.chars().take(column).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            let line_end = line_start + text.len();
+            let length = source
+                .get(label.span.start.min(line_end)..label.span.end.min(line_end))
+                .map_or(0, |underlined| underlined.chars().count())
+                .max(1);
+            let (mark, style) = if *is_primary { ('^', RED) } else { ('-', BLUE) };
+            let marks = paint(style, &format!("{} {}", mark.to_string().repeat(length), label.message));
+            let _ = writeln!(out, "{gutter} {indent}{}", marks.trim_end());
+        }
+
+        if !self.help.is_empty() {
+            if previous_line.is_some() {
+                let _ = writeln!(out, "{gutter}");
+            }
+            for help in &self.help {
+                let equals = paint(BLUE, &format!("{:width$} =", ""));
+                let _ = writeln!(out, "{equals} {}: {help}", paint(CYAN, "help"));
+            }
+        }
+        out
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Diagnostic::new(format!("syntax error: {}", error.message()))
+            .with_code(Some(error.code()))
+            .with_primary(error.span, "")
+    }
+}
+
+impl From<&CheckError> for Diagnostic {
+    fn from(error: &CheckError) -> Self {
+        let mut diagnostic = Diagnostic::new(&error.message).with_code(Some(error.code()));
+        if let Some(span) = error.span {
+            diagnostic = diagnostic.with_primary(span, "");
+        }
+        for (span, message) in &error.labels {
+            diagnostic = diagnostic.with_secondary(*span, message);
+        }
+        diagnostic
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        let (label, help) = match error.kind {
+            ErrorKind::UndefinedVariable => ("not defined here", Some("declare it first with `let`")),
+            ErrorKind::UndefinedFunction => ("no such function", Some("functions are declared at the top level with `fn`")),
+            ErrorKind::RecursionError => (
+                "call nested too deeply",
+                Some("raise the limit with --max-depth, or make the call a tail call with `return f(...)`"),
+            ),
+            _ => ("", None),
+        };
+
+        let mut diagnostic = Diagnostic::new(format!("uncaught {error}")).with_code(error.kind.code());
+        if let Some(span) = error.location {
+            diagnostic = diagnostic.with_primary(span, label);
+        }
+        if let Some(help) = help {
+            diagnostic = diagnostic.with_help(help);
+        }
+        diagnostic
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Evaluator;
+    use crate::{checker, parser};
+
+    #[test]
+    fn renders_runtime_errors_with_a_snippet() {
+        let source = "fn main() {\n    let a = 1;\n    print(a + missing);\n}";
+        let error = Evaluator::new().run(&parser::parse(source).unwrap()).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render("test.cheese", source, false),
+            "error[E0012]: uncaught UndefinedVariable: Undefined variable missing
+ --> test.cheese:3:15
+  |
+3 |     print(a + missing);
+  |               ^^^^^^^ not defined here
+  |
+  = help: declare it first with `let`
+"
+        );
+    }
+
+    #[test]
+    fn renders_secondary_labels() {
+        let source = "fn f() { }\n\nfn f() { }";
+        let errors = checker::check(&parser::parse(source).unwrap()).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&errors[0]).render("test.cheese", source, false),
+            "error[E0007]: Function 'f' is defined more than once
+ --> test.cheese:3:1
+  |
+1 | fn f() { }
+  | ---------- first defined here
+...
+3 | fn f() { }
+  | ^^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn renders_parse_errors_and_color() {
+        let source = "fn main() {\n    let x = * 2;\n}";
+        let errors = parser::parse(source).unwrap_err();
+        let plain = Diagnostic::from(&errors[0]).render("test.cheese", source, false);
+        assert!(plain.contains(" --> test.cheese:2:13\n"));
+        assert!(plain.contains("2 |     let x = * 2;\n  |             ^\n"));
+        assert!(!plain.contains('\x1b'));
+
+        let colored = Diagnostic::from(&errors[0]).render("test.cheese", source, true);
+        assert!(plain.starts_with("error[E0004]: syntax error: expected an expression, found `*`\n"));
+        assert!(colored.starts_with("\x1b[1;31merror[E0004]\x1b[0m"));
+    }
+}
@@ -0,0 +1,492 @@
+use std::ops::Range;
+
+use crate::ast::{AstNode, BinaryOperator, Parameter, Span};
+use crate::parser::{self, ParseError};
+
+const INDENT: &str = "    ";
+
+/// `source` laid out canonically: four-space indentation, one statement per
+/// line, spaces around binary operators and a `;` after every statement that
+/// does not end in a block. Comments and single blank lines are kept.
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    let program = parser::parse(source)?;
+    Ok(format_program(source, &program))
+}
+
+/// Like `format`, for a `program` already parsed from `source`.
+pub fn format_program(source: &str, program: &AstNode) -> String {
+    let mut formatter = Formatter {
+        source,
+        comments: parser::comments(source),
+        next_comment: 0,
+        depth: 0,
+        last_end: None,
+    };
+    formatter.program(program)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Range<usize>>,
+    /// The first comment not written yet. Comments are written in source
+    /// order, before the first statement or list item that starts after them.
+    next_comment: usize,
+    depth: usize,
+    /// Where the last statement or comment written ends; `None` at the start
+    /// of a block, where no blank line is kept.
+    last_end: Option<usize>,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &AstNode) -> String {
+        let mut out = String::new();
+        if self.source.starts_with("#!") {
+            let shebang = self.source.lines().next().unwrap_or_default();
+            out.push_str(shebang.trim_end());
+            out.push('\n');
+            self.last_end = Some(shebang.len());
+        }
+        let AstNode::Program(defs) = program.unspanned() else {
+            return out + &self.expression(program);
+        };
+        for (i, def) in defs.iter().enumerate() {
+            // Functions are always one blank line apart.
+            if i > 0 {
+                out.push('\n');
+            }
+            self.item(def, false, &mut out);
+        }
+        self.flush_comments(self.source.len(), &mut out);
+        out
+    }
+
+    /// Writes `node` on lines of its own, after the comments before it and
+    /// followed by a comment on the same line as its end.
+    fn item(&mut self, node: &AstNode, semicolon: bool, out: &mut String) {
+        let span = node.span();
+        if let Some(span) = span {
+            self.flush_comments(span.start, out);
+            self.blank_line_if_gap(span.start, out);
+        }
+        let text = self.statement(node);
+        out.push_str(&INDENT.repeat(self.depth));
+        out.push_str(&text);
+        if semicolon {
+            out.push(';');
+        }
+        if let Some(span) = span {
+            self.mark_written(span.end);
+            self.trailing_comment(span.end, out);
+        }
+        out.push('\n');
+    }
+
+    /// Writes the comments that start before `pos`, each on its own line.
+    fn flush_comments(&mut self, pos: usize, out: &mut String) {
+        while let Some(comment) = self.comments.get(self.next_comment).filter(|c| c.start < pos).cloned() {
+            self.blank_line_if_gap(comment.start, out);
+            out.push_str(&INDENT.repeat(self.depth));
+            out.push_str(self.source[comment.clone()].trim_end());
+            out.push('\n');
+            self.mark_written(comment.end);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Appends the next comment if only a `;` or `,` separates it from `end`.
+    fn trailing_comment(&mut self, end: usize, out: &mut String) {
+        let Some(comment) = self.comments.get(self.next_comment).cloned() else {
+            return;
+        };
+        if comment.start >= end && matches!(self.source[end..comment.start].trim_matches([' ', '\t']), "" | ";" | ",") {
+            out.push(' ');
+            out.push_str(self.source[comment.clone()].trim_end());
+            self.mark_written(comment.end);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Keeps one blank line where the source had any between the last thing
+    /// written and `pos`.
+    fn blank_line_if_gap(&self, pos: usize, out: &mut String) {
+        let Some(end) = self.last_end.filter(|&end| end < pos) else {
+            return;
+        };
+        let lines: Vec<&str> = self.source[end..pos].split('\n').collect();
+        let gap = lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|line| line.trim().is_empty());
+        if gap && !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+
+    fn mark_written(&mut self, end: usize) {
+        self.last_end = Some(self.last_end.map_or(end, |last| last.max(end)));
+    }
+
+    fn block(&mut self, block: &AstNode) -> String {
+        let AstNode::Block { statements, tail } = block.unspanned() else {
+            return self.expression(block);
+        };
+        let last_end = self.last_end.take();
+        self.depth += 1;
+        let mut out = String::new();
+        for (i, statement) in statements.iter().enumerate() {
+            let last = i + 1 == statements.len() && tail.is_none();
+            self.item(statement, needs_semicolon(statement, last), &mut out);
+        }
+        if let Some(tail) = tail {
+            self.item(tail, false, &mut out);
+        }
+        // Comments after the last statement, up to the closing `}`.
+        if let Some(span) = block.span() {
+            self.flush_comments(span.end.saturating_sub(1), &mut out);
+        }
+        self.depth -= 1;
+        self.last_end = last_end;
+        if out.is_empty() {
+            return "{}".to_string();
+        }
+        format!("{{\n{}{}}}", out, INDENT.repeat(self.depth))
+    }
+
+    fn statement(&mut self, node: &AstNode) -> String {
+        match node.unspanned() {
+            AstNode::FuncDef { name, args, body } => {
+                let args = self.statement(args);
+                format!("fn {}({}) {}", name, args, self.block(body))
+            }
+            AstNode::DefArgList(params) => {
+                let params: Vec<String> = params.iter().map(|param| self.parameter(param)).collect();
+                params.join(", ")
+            }
+            AstNode::VarDecl { name, value: None } => format!("let {}", name),
+            AstNode::VarDecl { name, value: Some(value) } => format!("let {} = {}", name, self.expression(value)),
+            AstNode::VarSet { target, op, value } => {
+                let target = self.expression(target);
+                let op = op.as_ref().map_or("", BinaryOperator::symbol);
+                format!("{} {}= {}", target, op, self.expression(value))
+            }
+            AstNode::FuncReturn(value) => format!("return {}", self.expression(value)),
+            AstNode::Yield(value) => format!("yield {}", self.expression(value)),
+            AstNode::Throw(value) => format!("throw {}", self.expression(value)),
+            AstNode::ForLoop { params, body } => {
+                let params = self.statement(params);
+                format!("for {} {}", params, self.block(body))
+            }
+            AstNode::ForLoopParams { initialization, condition, updater } => {
+                let initialization = self.statement(initialization);
+                let condition = self.expression(condition);
+                format!("({}; {}; {})", initialization, condition, self.statement(updater))
+            }
+            AstNode::ForInLoop { names, iterable, body } => {
+                let iterable = self.expression(iterable);
+                format!("for {} in {} {}", names.join(", "), iterable, self.block(body))
+            }
+            AstNode::WhileLoop { condition, body } => {
+                let condition = self.expression(condition);
+                format!("while {} {}", condition, self.block(body))
+            }
+            AstNode::TryCatch { body, catch, finally } => {
+                let mut text = format!("try {}", self.block(body));
+                if let Some((name, handler)) = catch {
+                    text += &format!(" catch {} {}", name, self.block(handler));
+                }
+                if let Some(finally) = finally {
+                    text += &format!(" finally {}", self.block(finally));
+                }
+                text
+            }
+            _ => self.expression(node),
+        }
+    }
+
+    fn parameter(&mut self, param: &Parameter) -> String {
+        match &param.default {
+            _ if param.rest => format!("...{}", param.name),
+            Some(default) => format!("{} = {}", param.name, self.expression(default)),
+            None => param.name.clone(),
+        }
+    }
+
+    fn expression(&mut self, node: &AstNode) -> String {
+        match node.unspanned() {
+            AstNode::Expression(inner) => self.expression(inner),
+            AstNode::Term(inner) => self.operand(inner),
+            AstNode::Propagate(inner) => format!("{}?", self.operand(inner)),
+            AstNode::Index { target, index } => {
+                let target = self.operand(target);
+                format!("{}[{}]", target, self.expression(index))
+            }
+            AstNode::BinaryExpression { lhs, op, rhs } => {
+                let lhs = self.expression(lhs);
+                format!("{} {} {}", lhs, op.symbol(), self.expression(rhs))
+            }
+            AstNode::UnaryExpression { op, child } => format!("{}{}", op.symbol(), self.expression(child)),
+            AstNode::Range { start, end, inclusive, step } => {
+                let start = self.expression(start);
+                let end = self.expression(end);
+                let mut text = format!("{}{}{}", start, if *inclusive { "..=" } else { ".." }, end);
+                if let Some(step) = step {
+                    text += &format!(" step {}", self.expression(step));
+                }
+                text
+            }
+            // The parser builds `c ? a : b` as an `if` whose branches are not blocks.
+            AstNode::IfStatement { condition, body, else_body: Some(otherwise) }
+                if !matches!(body.unspanned(), AstNode::Block { .. }) =>
+            {
+                let condition = self.expression(condition);
+                let then = self.expression(body);
+                format!("{} ? {} : {}", condition, then, self.expression(otherwise))
+            }
+            AstNode::IfStatement { condition, body, else_body } => {
+                let condition = self.expression(condition);
+                let mut text = format!("if {} {}", condition, self.block(body));
+                if let Some(else_body) = else_body {
+                    text += &format!(" else {}", self.expression(else_body));
+                }
+                text
+            }
+            AstNode::Block { .. } => self.block(node),
+            AstNode::FuncCall { name, args } => match args.unspanned() {
+                AstNode::ArgList(args) => format!("{}{}", name, self.list(args, ("(", ")"), node.span())),
+                _ => format!("{}({})", name, self.expression(args)),
+            },
+            AstNode::ArgList(args) => self.list(args, ("", ""), None),
+            AstNode::NamedArg { name, value } => format!("{}: {}", name, self.expression(value)),
+            AstNode::List(items) => self.list(items, ("[", "]"), node.span()),
+            AstNode::Int(n) => n.to_string(),
+            AstNode::Str(s) => format!("\"{}\"", s),
+            AstNode::Identifier(name) => name.clone(),
+            AstNode::Boolean(true) => "True".to_string(),
+            AstNode::Boolean(false) => "False".to_string(),
+            AstNode::Nil => "None".to_string(),
+            AstNode::Program(_) => self.program(node),
+            AstNode::FuncDef { .. }
+            | AstNode::DefArgList(_)
+            | AstNode::VarDecl { .. }
+            | AstNode::VarSet { .. }
+            | AstNode::FuncReturn(_)
+            | AstNode::Yield(_)
+            | AstNode::Throw(_)
+            | AstNode::ForLoop { .. }
+            | AstNode::ForLoopParams { .. }
+            | AstNode::ForInLoop { .. }
+            | AstNode::WhileLoop { .. }
+            | AstNode::TryCatch { .. } => self.statement(node),
+            AstNode::Spanned(_, inner) => self.expression(inner),
+        }
+    }
+
+    /// A term, in the parentheses it was written with.
+    fn operand(&mut self, node: &AstNode) -> String {
+        match node.unspanned() {
+            AstNode::Expression(_) => format!("({})", self.expression(node)),
+            _ => self.expression(node),
+        }
+    }
+
+    /// `items` between `brackets`, one per line when a comment inside `span`
+    /// has to stay with the item it follows.
+    fn list(&mut self, items: &[Box<AstNode>], brackets: (&str, &str), span: Option<Span>) -> String {
+        let (open, close) = brackets;
+        let commented = span.is_some_and(|span| {
+            self.comments.get(self.next_comment).is_some_and(|c| c.start > span.start && c.start < span.end)
+        });
+        if !commented {
+            let items: Vec<String> = items.iter().map(|item| self.expression(item)).collect();
+            return format!("{}{}{}", open, items.join(", "), close);
+        }
+
+        self.depth += 1;
+        let mut out = String::new();
+        for (i, item) in items.iter().enumerate() {
+            // Named arguments carry no span of their own.
+            let item_span = match item.unspanned() {
+                AstNode::NamedArg { value, .. } => value.span(),
+                _ => item.span(),
+            };
+            if let Some(item_span) = item_span {
+                self.flush_comments(item_span.start, &mut out);
+            }
+            out.push_str(&INDENT.repeat(self.depth));
+            out.push_str(&self.expression(item));
+            if i + 1 < items.len() {
+                out.push(',');
+            }
+            if let Some(item_span) = item_span {
+                self.mark_written(item_span.end);
+                self.trailing_comment(item_span.end, &mut out);
+            }
+            out.push('\n');
+        }
+        // Comments after the last item, up to the closing bracket.
+        if let Some(span) = span {
+            self.flush_comments(span.end.saturating_sub(1), &mut out);
+        }
+        self.depth -= 1;
+        format!("{}\n{}{}{}", open, out, INDENT.repeat(self.depth), close)
+    }
+}
+
+/// Whether `statement` is written with a `;`. A trailing `if` or block keeps
+/// its `;` as the last statement of a block, where leaving it out would make
+/// it the block's value.
+fn needs_semicolon(statement: &AstNode, last: bool) -> bool {
+    match statement.unspanned() {
+        AstNode::WhileLoop { .. } | AstNode::ForLoop { .. } | AstNode::ForInLoop { .. } | AstNode::TryCatch { .. } => false,
+        AstNode::IfStatement { .. } | AstNode::Block { .. } => last,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The debug form of `source`'s syntax tree, without positions.
+    fn shape(source: &str) -> String {
+        let mut tree = format!("{:?}", parser::parse(source).unwrap());
+        while let Some(start) = tree.find("Span {") {
+            let end = start + tree[start..].find('}').unwrap();
+            tree.replace_range(start..=end, "Span");
+        }
+        tree
+    }
+
+    #[test]
+    fn lays_out_programs_canonically() {
+        let source = "fn add(a,b=1,...rest){return a+b*(rest[0]??0);}
+fn main( ) {let xs=[1,2,3,];for(let i=0;i<3;i+=1){print(xs[i]);};
+  while False {}
+let y = if True { 1 } else if False { 2 } else { -3 }; let t = y > 0 ? \"pos\" : \"neg\";
+    try { throw \"x\"; } catch e { print(e, sep: \" \"); } finally { print(0..=10 step 2); }
+  for k, v in dict(\"a\", 1) { yield k; }
+    if True { 1 };
+}";
+        let expected = "fn add(a, b = 1, ...rest) {
+    return a + b * (rest[0] ?? 0);
+}
+
+fn main() {
+    let xs = [1, 2, 3];
+    for (let i = 0; i < 3; i += 1) {
+        print(xs[i]);
+    }
+    while False {}
+    let y = if True {
+        1
+    } else if False {
+        2
+    } else {
+        -3
+    };
+    let t = y > 0 ? \"pos\" : \"neg\";
+    try {
+        throw \"x\";
+    } catch e {
+        print(e, sep: \" \");
+    } finally {
+        print(0..=10 step 2);
+    }
+    for k, v in dict(\"a\", 1) {
+        yield k;
+    }
+    if True {
+        1
+    };
+}
+";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(shape(source), shape(expected));
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "#!/usr/bin/env firstlang
+// Greets.
+
+
+fn main() { // entry
+    let a = 1;   // one
+
+
+    // two
+    let b = 2;
+    if a { print(a); } // done
+    // nothing left
+}
+// The end.
+";
+        let expected = "#!/usr/bin/env firstlang
+// Greets.
+
+fn main() {
+    // entry
+    let a = 1; // one
+
+    // two
+    let b = 2;
+    if a {
+        print(a);
+    } // done
+    // nothing left
+}
+// The end.
+";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn keeps_comments_inside_arguments_and_lists() {
+        let source = "fn main() {
+    print(1, // first
+        2);
+    let xs = [
+        // head
+        1, // one
+        f(2, // two
+          x: 3) // three
+    ];
+}
+";
+        let expected = "fn main() {
+    print(
+        1, // first
+        2
+    );
+    let xs = [
+        // head
+        1, // one
+        f(
+            2, // two
+            x: 3
+        ) // three
+    ];
+}
+";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+        assert_eq!(shape(source), shape(expected));
+    }
+
+    #[test]
+    fn keeps_the_semicolon_that_decides_a_block_value() {
+        let source = "fn f() { if True { 1 } }\n\nfn g() { if True { 1 }; }\n";
+        assert_eq!(shape(&format(source).unwrap()), shape(source));
+    }
+
+    #[test]
+    fn formatting_the_examples_is_idempotent() {
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let formatted = format(&source).unwrap();
+            assert_eq!(shape(&formatted), shape(&source), "{} changed meaning", path.display());
+            assert_eq!(format(&formatted).unwrap(), formatted, "{} is not stable", path.display());
+        }
+        let hello = std::fs::read_to_string("examples/hello.cheese").unwrap();
+        assert_eq!(format(&hello).unwrap(), hello);
+    }
+}
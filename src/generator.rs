@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::AstNode;
+use crate::interpreter::{ErrorKind, Evaluator, RuntimeError, Unwind, Value};
+use crate::iterator::Iter;
+
+/// A suspended generator call. Statements that may reach a `yield` are run one at
+/// a time from an explicit frame stack, so the body can pause mid-`Block` or
+/// mid-loop and continue from the same point on the next `next()`.
+#[derive(Debug)]
+pub struct GeneratorState {
+    name: String,
+    scope: HashMap<String, Value>,
+    frames: Vec<Frame>,
+}
+
+/// Loop bodies are split into statements once, when the loop is entered, and
+/// shared by the `Block` frame of every iteration.
+#[derive(Debug)]
+enum Frame {
+    Block {
+        statements: Rc<[AstNode]>,
+        index: usize,
+    },
+    While {
+        condition: Rc<AstNode>,
+        body: Rc<[AstNode]>,
+    },
+    For {
+        condition: Rc<AstNode>,
+        updater: Rc<AstNode>,
+        body: Rc<[AstNode]>,
+        started: bool,
+    },
+    ForIn {
+        names: Vec<String>,
+        iter: Rc<RefCell<Iter>>,
+        body: Rc<[AstNode]>,
+    },
+}
+
+/// The statements of a block, tail included, to step through one at a time.
+/// Anything else, like an `else if` chain, is a block of one statement.
+fn statements_of(node: &AstNode) -> Rc<[AstNode]> {
+    match node.unspanned() {
+        AstNode::Block { statements, tail } => {
+            statements.iter().chain(tail).map(|statement| (**statement).clone()).collect()
+        }
+        _ => Rc::new([node.clone()]),
+    }
+}
+
+impl GeneratorState {
+    pub fn new(name: String, scope: HashMap<String, Value>, body: Rc<AstNode>) -> Self {
+        GeneratorState {
+            name,
+            scope,
+            frames: vec![Frame::Block {
+                statements: statements_of(&body),
+                index: 0,
+            }],
+        }
+    }
+}
+
+impl Evaluator {
+    /// Runs a generator until its next `yield`, returning `None` once the body
+    /// finishes or executes `return`.
+    pub fn resume_generator(&mut self, state: &mut GeneratorState) -> Result<Option<Value>, Unwind> {
+        self.enter_call(&state.name)?;
+        self.symbol_table.push_frame(std::mem::take(&mut state.scope));
+        let result = match self.step_generator(&mut state.frames) {
+            // `return f()` still calls `f`, inside the generator's scope.
+            Err(Unwind::TailCall(call)) => self
+                .call_value_with(&call.func, call.positional, call.named)
+                .map(|_| None),
+            result => result,
+        };
+        state.scope = self.symbol_table.pop_frame();
+        let result = self.leave_call(result);
+
+        match result {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) | Err(Unwind::Return(_)) => {
+                state.frames.clear();
+                Ok(None)
+            }
+            Err(error) => {
+                state.frames.clear();
+                Err(error)
+            }
+        }
+    }
+
+    fn step_generator(&mut self, frames: &mut Vec<Frame>) -> Result<Option<Value>, Unwind> {
+        while let Some(frame) = frames.last_mut() {
+            match frame {
+                Frame::Block { statements, index } => {
+                    let statements = statements.clone();
+                    let Some(statement) = statements.get(*index) else {
+                        frames.pop();
+                        continue;
+                    };
+                    *index += 1;
+
+                    if !statement.contains_yield() {
+                        self.eval(statement)?;
+                        continue;
+                    }
+                    match statement.unwrap_expression() {
+                        AstNode::Yield(expr) => return Ok(Some(self.eval(expr)?)),
+                        block @ AstNode::Block { .. } => frames.push(Frame::Block {
+                            statements: statements_of(block),
+                            index: 0,
+                        }),
+                        AstNode::IfStatement { condition, body, else_body } => {
+                            let branch = if let Value::Boolean(true) = self.eval(condition)? {
+                                Some(body)
+                            } else {
+                                else_body.as_ref()
+                            };
+                            if let Some(branch) = branch {
+                                frames.push(Frame::Block {
+                                    statements: statements_of(branch),
+                                    index: 0,
+                                });
+                            }
+                        }
+                        AstNode::WhileLoop { condition, body } => frames.push(Frame::While {
+                            condition: Rc::new((**condition).clone()),
+                            body: statements_of(body),
+                        }),
+                        AstNode::ForLoop { params, body } => {
+                            let AstNode::ForLoopParams { initialization, condition, updater } = &**params else {
+                                return Err(Unwind::error(ErrorKind::InternalError, "Invalid for loop parameters"));
+                            };
+                            self.eval(initialization)?;
+                            frames.push(Frame::For {
+                                condition: Rc::new((**condition).clone()),
+                                updater: Rc::new((**updater).clone()),
+                                body: statements_of(body),
+                                started: false,
+                            });
+                        }
+                        AstNode::ForInLoop { names, iterable, body } => {
+                            let iter = Iter::from_value(&self.eval(iterable)?)?;
+                            frames.push(Frame::ForIn {
+                                names: names.clone(),
+                                iter,
+                                body: statements_of(body),
+                            });
+                        }
+                        // The checker rejects this, so only unchecked programs get here.
+                        _ => {
+                            let mut error = RuntimeError::new(ErrorKind::SyntaxError, "yield is not supported inside try blocks");
+                            error.location = statement.span();
+                            return Err(Unwind::Throw(Value::Error(Box::new(error))));
+                        }
+                    }
+                }
+                Frame::While { condition, body } => {
+                    let body = body.clone();
+                    if let Value::Boolean(true) = self.eval(&condition.clone())? {
+                        frames.push(Frame::Block { statements: body, index: 0 });
+                    } else {
+                        frames.pop();
+                    }
+                }
+                Frame::For { condition, updater, body, started } => {
+                    let (condition, updater, body) = (condition.clone(), updater.clone(), body.clone());
+                    if *started {
+                        self.eval(&updater)?;
+                    }
+                    *started = true;
+                    if let Value::Boolean(true) = self.eval(&condition)? {
+                        frames.push(Frame::Block { statements: body, index: 0 });
+                    } else {
+                        frames.pop();
+                    }
+                }
+                Frame::ForIn { names, iter, body } => {
+                    let (names, iter, body) = (names.clone(), iter.clone(), body.clone());
+                    match self.next_item(&iter)? {
+                        Some(item) => {
+                            self.bind_loop_variables(&names, item)?;
+                            frames.push(Frame::Block { statements: body, index: 0 });
+                        }
+                        None => {
+                            frames.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
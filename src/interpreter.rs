@@ -1,67 +1,648 @@
-use core::panic;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
 
 use crate::ast::AstNode;
 use crate::ast::BinaryOperator; 
+use crate::ast::Parameter;
+use crate::ast::Span;
 use crate::ast::UnaryOperator;
 
 use crate::builtins::Builtins;
 use crate::builtins::BuiltinFunction;
+use crate::generator::GeneratorState;
+use crate::iterator::Iter;
 
-#[derive(Debug, Clone)] pub enum Value {
+#[derive(Debug)] pub enum Value {
     Integer(i32),
     String(String),
     Boolean(bool),
-    Function(String, Vec<String>, Box<AstNode>),
+    Nil,
+    Error(Box<RuntimeError>),
+    Ok(Box<Value>),
+    Err(Box<Value>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Vec<(Value, Value)>>>),
+    Range {
+        start: i32,
+        end: i32,
+        step: i32,
+        inclusive: bool,
+    },
+    Iterator(Rc<RefCell<Iter>>),
+    Function(String, Rc<Vec<Parameter>>, Rc<AstNode>),
+    /// A function whose body contains `yield`; calling it returns an iterator.
+    GeneratorFunction(String, Rc<Vec<Parameter>>, Rc<AstNode>),
     BuiltinFunction(BuiltinFunction),
 }
 
-#[derive(Clone)]
-struct SymbolTable {
-    symbols: HashMap<String, Value>,
-    parent: Option<Box<SymbolTable>>,
+/// What went wrong in a `RuntimeError`. Cheese code sees the variant name as a
+/// string through `error_kind()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UndefinedVariable,
+    UndefinedFunction,
+    /// An operand or argument of the wrong type.
+    TypeMismatch,
+    /// Too many, too few or unknown arguments in a call.
+    ArityMismatch,
+    DivisionByZero,
+    /// Integer arithmetic whose result does not fit in 32 bits.
+    Overflow,
+    IndexError,
+    /// A value of the right type that is still unusable, like `int("abc")`.
+    ValueError,
+    RecursionError,
+    /// Reading input failed.
+    IoError,
+    /// Code the checker rejects, evaluated without being checked.
+    SyntaxError,
+    InvalidMain,
+    /// A non-error value was thrown and never caught.
+    UncaughtThrow,
+    InternalError,
+    /// A kind named by cheese code through `error(kind, message)`.
+    Custom(String),
 }
 
-impl SymbolTable {
-    fn new() -> Self {
-        SymbolTable {
-            symbols: HashMap::new(),
-            parent: None,
+impl ErrorKind {
+    const BUILTIN: [ErrorKind; 14] = [
+        ErrorKind::UndefinedVariable,
+        ErrorKind::UndefinedFunction,
+        ErrorKind::TypeMismatch,
+        ErrorKind::ArityMismatch,
+        ErrorKind::DivisionByZero,
+        ErrorKind::Overflow,
+        ErrorKind::IndexError,
+        ErrorKind::ValueError,
+        ErrorKind::RecursionError,
+        ErrorKind::IoError,
+        ErrorKind::SyntaxError,
+        ErrorKind::InvalidMain,
+        ErrorKind::UncaughtThrow,
+        ErrorKind::InternalError,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            ErrorKind::UndefinedVariable => "UndefinedVariable",
+            ErrorKind::UndefinedFunction => "UndefinedFunction",
+            ErrorKind::TypeMismatch => "TypeMismatch",
+            ErrorKind::ArityMismatch => "ArityMismatch",
+            ErrorKind::DivisionByZero => "DivisionByZero",
+            ErrorKind::Overflow => "Overflow",
+            ErrorKind::IndexError => "IndexError",
+            ErrorKind::ValueError => "ValueError",
+            ErrorKind::RecursionError => "RecursionError",
+            ErrorKind::IoError => "IoError",
+            ErrorKind::SyntaxError => "SyntaxError",
+            ErrorKind::InvalidMain => "InvalidMain",
+            ErrorKind::UncaughtThrow => "UncaughtThrow",
+            ErrorKind::InternalError => "InternalError",
+            ErrorKind::Custom(name) => name,
         }
     }
 
-    fn with_parent(parent: Box<SymbolTable>) -> Self {
-        SymbolTable {
-            symbols: HashMap::new(),
-            parent: Some(parent),
+    /// The stable code `firstlang explain` knows this kind by. Kinds made up
+    /// by cheese code have none.
+    pub fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            ErrorKind::UndefinedVariable => "E0012",
+            ErrorKind::UndefinedFunction => "E0013",
+            ErrorKind::TypeMismatch => "E0014",
+            ErrorKind::ArityMismatch => "E0015",
+            ErrorKind::DivisionByZero => "E0016",
+            ErrorKind::Overflow => "E0017",
+            ErrorKind::IndexError => "E0018",
+            ErrorKind::ValueError => "E0019",
+            ErrorKind::RecursionError => "E0020",
+            ErrorKind::IoError => "E0021",
+            ErrorKind::SyntaxError => "E0022",
+            ErrorKind::InvalidMain => "E0023",
+            ErrorKind::UncaughtThrow => "E0024",
+            ErrorKind::InternalError => "E0025",
+            ErrorKind::Custom(_) => return None,
+        })
+    }
+
+    /// The kind called `name`, so `error("TypeMismatch", ...)` in cheese matches
+    /// errors raised by the interpreter.
+    pub fn from_name(name: &str) -> ErrorKind {
+        ErrorKind::BUILTIN
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .unwrap_or_else(|| ErrorKind::Custom(name.to_string()))
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// An error raised while evaluating a program. Inside cheese it is an ordinary
+/// value that `catch` binds and `throw` re-raises.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Where the error was raised, if known.
+    pub location: Option<Span>,
+    /// Calls that were active when the error left its innermost call, outermost
+    /// first. Frames replaced by tail calls are not included.
+    pub stack: Vec<StackFrame>,
+}
+
+/// One active call: the function and where it was called from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub call_site: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            location: None,
+            stack: vec![],
         }
     }
 
-    fn get(&self, name: &str) -> Option<Value> {
-        match self.symbols.get(name) {
-            Some(value) => Some(value.clone()),
-            None => match &self.parent {
-                Some(parent) => parent.get(name),
-                None => None,
-            },
+    /// The call stack innermost first, one `at function (file:line:col)` line
+    /// per frame. Runs of identical frames, as deep recursion produces, are
+    /// collapsed into a count.
+    pub fn backtrace(&self, file: &str) -> String {
+        self.backtrace_in(|_| file.to_string())
+    }
+
+    /// `backtrace`, for code from several sources: each frame's position is in
+    /// the file `file_of` gives for its function.
+    pub fn backtrace_in(&self, file_of: impl Fn(&str) -> String) -> String {
+        let mut frames = vec![];
+        let mut location = self.location;
+        for frame in self.stack.iter().rev() {
+            let file = file_of(&frame.function);
+            frames.push(match location {
+                Some(span) => format!("    at {}() ({}:{}:{})", frame.function, file, span.line, span.col),
+                None => format!("    at {}() ({})", frame.function, file),
+            });
+            location = frame.call_site;
+        }
+
+        let mut lines = vec![];
+        let mut rest = frames.as_slice();
+        while let Some(frame) = rest.first() {
+            let run = rest.iter().take_while(|other| *other == frame).count();
+            lines.push(frame.clone());
+            if run > 1 {
+                lines.push(format!("    [previous frame repeated {} more times]", run - 1));
+            }
+            rest = &rest[run..];
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl Value {
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    /// Moves the value out, leaving `None` behind. `Value` implements `Drop`, so
+    /// this is how an `Ok` or `Err` gives up what it holds.
+    pub(crate) fn take(&mut self) -> Value {
+        std::mem::replace(self, Value::Nil)
+    }
+
+    pub fn new_list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn new_map(entries: Vec<(Value, Value)>) -> Value {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Looks `key` up in the entries of a `Value::Map`.
+    pub fn map_get(entries: &[(Value, Value)], key: &Value) -> Option<Value> {
+        entries
+            .iter()
+            .find(|(k, _)| k.equals(key))
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Inserts or replaces `key` in the entries of a `Value::Map`, keeping insertion order.
+    /// The key is looked up before the entries are borrowed mutably, as it may be
+    /// or contain the map itself.
+    pub fn map_insert(entries: &RefCell<Vec<(Value, Value)>>, key: Value, value: Value) {
+        let found = entries.borrow().iter().position(|(k, _)| k.equals(&key));
+        let mut entries = entries.borrow_mut();
+        match found {
+            Some(i) => entries[i].1 = value,
+            None => entries.push((key, value)),
+        }
+    }
+
+    /// The value as the REPL echoes it: like `Display`, but with strings quoted.
+    pub fn repr(&self) -> String {
+        format!("{:?}", DisplayNested(self))
+    }
+
+    /// Structural equality as seen by `==` and `!=`; values of different types are never equal.
+    pub fn equals(&self, other: &Value) -> bool {
+        self.equals_within(other, &mut HashSet::new())
+    }
+
+    /// `equals`, where `pending` holds the pairs of lists and maps already being
+    /// compared further up, so cyclic values compare equal instead of recursing
+    /// forever. See `EVAL_RED_ZONE` for why it may switch stacks.
+    fn equals_within(&self, other: &Value, pending: &mut HashSet<(*const (), *const ())>) -> bool {
+        stacker::maybe_grow(EVAL_RED_ZONE, EVAL_STACK_SIZE, || self.equals_now(other, pending))
+    }
+
+    fn equals_now(&self, other: &Value, pending: &mut HashSet<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Value::Integer(l), Value::Integer(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Error(l), Value::Error(r)) => l.kind == r.kind && l.message == r.message,
+            (Value::Ok(l), Value::Ok(r)) | (Value::Err(l), Value::Err(r)) => l.equals_within(r, pending),
+            (Value::List(l), Value::List(r)) => {
+                let pair = (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ());
+                if Rc::ptr_eq(l, r) || !pending.insert(pair) {
+                    return true;
+                }
+                let (l, r) = (l.borrow(), r.borrow());
+                let equal = l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| l.equals_within(r, pending));
+                pending.remove(&pair);
+                equal
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                let pair = (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ());
+                if Rc::ptr_eq(l, r) || !pending.insert(pair) {
+                    return true;
+                }
+                let (l, r) = (l.borrow(), r.borrow());
+                let equal = l.len() == r.len()
+                    && l.iter().all(|(k, v)| {
+                        r.iter()
+                            .find(|(other, _)| other.equals_within(k, pending))
+                            .is_some_and(|(_, other)| v.equals_within(other, pending))
+                    });
+                pending.remove(&pair);
+                equal
+            }
+            (
+                Value::Range { start, end, step, inclusive },
+                Value::Range { start: s, end: e, step: st, inclusive: i },
+            ) => (start, end, step, inclusive) == (s, e, st, i),
+            (Value::Iterator(l), Value::Iterator(r)) => Rc::ptr_eq(l, r),
+            (Value::Function(l, _, _), Value::Function(r, _, _))
+            | (Value::GeneratorFunction(l, _, _), Value::GeneratorFunction(r, _, _)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+/// Only `Ok` and `Err` own what they hold, so only they are cloned deeply,
+/// on a new stack when needed (see `EVAL_RED_ZONE`).
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        match self {
+            Value::Integer(i) => Value::Integer(*i),
+            Value::String(s) => Value::String(s.clone()),
+            Value::Boolean(b) => Value::Boolean(*b),
+            Value::Nil => Value::Nil,
+            Value::Error(error) => Value::Error(error.clone()),
+            Value::Ok(inner) => Value::Ok(Box::new(stacker::maybe_grow(EVAL_RED_ZONE, EVAL_STACK_SIZE, || inner.as_ref().clone()))),
+            Value::Err(inner) => Value::Err(Box::new(stacker::maybe_grow(EVAL_RED_ZONE, EVAL_STACK_SIZE, || inner.as_ref().clone()))),
+            Value::List(items) => Value::List(items.clone()),
+            Value::Map(entries) => Value::Map(entries.clone()),
+            Value::Range { start, end, step, inclusive } => {
+                Value::Range { start: *start, end: *end, step: *step, inclusive: *inclusive }
+            }
+            Value::Iterator(iter) => Value::Iterator(iter.clone()),
+            Value::Function(name, params, body) => Value::Function(name.clone(), params.clone(), body.clone()),
+            Value::GeneratorFunction(name, params, body) => {
+                Value::GeneratorFunction(name.clone(), params.clone(), body.clone())
+            }
+            Value::BuiltinFunction(func) => Value::BuiltinFunction(*func),
+        }
+    }
+}
+
+/// Values nested thousands deep, like a list built by `xs = [xs]` in a loop,
+/// would overflow the stack if dropped recursively. Instead, the contents of
+/// each list, map or result dropped for the last time are moved onto a work
+/// stack and dropped one at a time.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        take_contents(self, &mut pending);
+        while let Some(mut value) = pending.pop() {
+            take_contents(&mut value, &mut pending);
+        }
+    }
+}
+
+/// Moves what `value` holds onto `pending`, if nothing else shares it.
+fn take_contents(value: &mut Value, pending: &mut Vec<Value>) {
+    match value {
+        Value::Ok(inner) | Value::Err(inner) => pending.push(inner.take()),
+        Value::List(items) => {
+            if let Some(items) = Rc::get_mut(items) {
+                pending.append(items.get_mut());
+            }
+        }
+        Value::Map(entries) => {
+            if let Some(entries) = Rc::get_mut(entries) {
+                for (key, value) in entries.get_mut().drain(..) {
+                    pending.extend([key, value]);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self, false, &mut HashSet::new())
+    }
+}
+
+/// Writes `value`, quoting it if it is a string and `quoted` is set. `open`
+/// holds the lists and maps being written further up; one that contains
+/// itself is written as `[...]` or `{...}` where it recurs. See
+/// `EVAL_RED_ZONE` for why it may switch stacks.
+fn write_value(f: &mut fmt::Formatter<'_>, value: &Value, quoted: bool, open: &mut HashSet<*const ()>) -> fmt::Result {
+    stacker::maybe_grow(EVAL_RED_ZONE, EVAL_STACK_SIZE, || write_value_now(f, value, quoted, open))
+}
+
+fn write_value_now(f: &mut fmt::Formatter<'_>, value: &Value, quoted: bool, open: &mut HashSet<*const ()>) -> fmt::Result {
+    match value {
+        Value::Integer(i) => write!(f, "{}", i),
+        Value::String(s) if quoted => write!(f, "{:?}", s),
+        Value::String(s) => write!(f, "{}", s),
+        Value::Boolean(b) => write!(f, "{}", b),
+        Value::Nil => write!(f, "None"),
+        Value::Error(error) => write!(f, "{}", error),
+        Value::Ok(inner) => {
+            write!(f, "Ok(")?;
+            write_value(f, inner, false, open)?;
+            write!(f, ")")
+        }
+        Value::Err(inner) => {
+            write!(f, "Err(")?;
+            write_value(f, inner, false, open)?;
+            write!(f, ")")
+        }
+        Value::List(items) => {
+            let ptr = Rc::as_ptr(items) as *const ();
+            if !open.insert(ptr) {
+                return write!(f, "[...]");
+            }
+            write!(f, "[")?;
+            for (i, item) in items.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_value(f, item, true, open)?;
+            }
+            open.remove(&ptr);
+            write!(f, "]")
+        }
+        Value::Map(entries) => {
+            let ptr = Rc::as_ptr(entries) as *const ();
+            if !open.insert(ptr) {
+                return write!(f, "{{...}}");
+            }
+            write!(f, "{{")?;
+            for (i, (key, value)) in entries.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_value(f, key, true, open)?;
+                write!(f, ": ")?;
+                write_value(f, value, true, open)?;
+            }
+            open.remove(&ptr);
+            write!(f, "}}")
+        }
+        Value::Range { start, end, step, inclusive } => {
+            write!(f, "{}{}{}", start, if *inclusive { "..=" } else { ".." }, end)?;
+            if *step != 1 {
+                write!(f, " step {}", step)?;
+            }
+            Ok(())
+        }
+        Value::Iterator(_) => write!(f, "<iterator>"),
+        Value::Function(name, _, _) | Value::GeneratorFunction(name, _, _) => write!(f, "<fn {}>", name),
+        Value::BuiltinFunction(_) => write!(f, "<builtin fn>"),
+    }
+}
+
+/// The result of checked integer arithmetic, or an `Overflow` error naming the operation.
+fn checked(result: Option<i32>, op: &str, l: i32, r: i32) -> Result<Value, Unwind> {
+    result
+        .map(Value::Integer)
+        .ok_or_else(|| Unwind::error(ErrorKind::Overflow, format!("Integer overflow in {} {} {}", l, op, r)))
+}
+
+fn binary_operation(op: &BinaryOperator, left: Value, right: Value) -> Result<Value, Unwind> {
+    match (left, right) {
+        (Value::Integer(l), Value::Integer(r)) => match op {
+            BinaryOperator::Add => checked(l.checked_add(r), "+", l, r),
+            BinaryOperator::Subtract => checked(l.checked_sub(r), "-", l, r),
+            BinaryOperator::Multiply => checked(l.checked_mul(r), "*", l, r),
+            BinaryOperator::Divide | BinaryOperator::Modulo if r == 0 => {
+                Err(Unwind::error(ErrorKind::DivisionByZero, "Division by zero"))
+            }
+            BinaryOperator::Divide => checked(l.checked_div(r), "/", l, r),
+            BinaryOperator::Modulo => checked(l.checked_rem(r), "%", l, r),
+            BinaryOperator::Greater => Ok(Value::Boolean(l > r)),
+            BinaryOperator::Less => Ok(Value::Boolean(l < r)),
+            BinaryOperator::Equal => Ok(Value::Boolean(l == r)),
+            BinaryOperator::NotEqual => Ok(Value::Boolean(l != r)),
+            BinaryOperator::GreaterEq => Ok(Value::Boolean(l >= r)),
+            BinaryOperator::LessEq => Ok(Value::Boolean(l <= r)),
+            // `??` short-circuits in `eval_node` and never gets here.
+            BinaryOperator::NullCoalesce => {
+                Err(Unwind::error(ErrorKind::InternalError, "?? must be evaluated lazily"))
+            }
+        },
+        (l, r) if matches!(op, BinaryOperator::Equal) => Ok(Value::Boolean(l.equals(&r))),
+        (l, r) if matches!(op, BinaryOperator::NotEqual) => Ok(Value::Boolean(!l.equals(&r))),
+        _ => Err(Unwind::error(ErrorKind::TypeMismatch, "Invalid operands for binary expression")),
+    }
+}
+
+fn index_value(target: &Value, index: &Value) -> Result<Value, Unwind> {
+    match (target, index) {
+        (Value::List(items), Value::Integer(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| items.borrow().get(i).cloned())
+            .ok_or_else(|| Unwind::error(ErrorKind::IndexError, format!("List index {} out of range", i))),
+        (Value::String(s), Value::Integer(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| s.chars().nth(i))
+            .map(|c| Value::String(c.to_string()))
+            .ok_or_else(|| Unwind::error(ErrorKind::IndexError, format!("String index {} out of range", i))),
+        (Value::Map(entries), key) => Ok(Value::map_get(&entries.borrow(), key).unwrap_or(Value::Nil)),
+        _ => Err(Unwind::error(ErrorKind::TypeMismatch, format!("Cannot index {} with {}", target, index))),
+    }
+}
+
+fn assign_index(target: &Value, index: Value, value: Value) -> Result<(), Unwind> {
+    match (target, &index) {
+        (Value::List(items), Value::Integer(i)) => {
+            let mut items = items.borrow_mut();
+            match usize::try_from(*i).ok().and_then(|i| items.get_mut(i)) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(Unwind::error(ErrorKind::IndexError, format!("List index {} out of range", i))),
+            }
+        }
+        (Value::Map(entries), _) => {
+            Value::map_insert(entries, index, value);
+            Ok(())
         }
+        _ => Err(Unwind::error(ErrorKind::TypeMismatch, format!("Cannot assign to index {} of {}", index, target))),
+    }
+}
+
+/// Formats values inside lists and maps, quoting strings so `["1"]` and `[1]` differ.
+struct DisplayNested<'a>(&'a Value);
+
+impl fmt::Debug for DisplayNested<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self.0, true, &mut HashSet::new())
+    }
+}
+
+/// Non-local exits that unwind through `Evaluator::eval`.
+pub enum Unwind {
+    Return(Value),
+    Throw(Value),
+    /// `return f(...)` for a cheese function `f`: the caller's frame is discarded
+    /// before `f` runs, so tail recursion loops in `call_value_with` instead of
+    /// growing the Rust stack.
+    TailCall(Box<TailCall>),
+    /// `exit(code)`: ends the program from any depth. `catch` does not stop it,
+    /// though `finally` blocks still run.
+    Exit(i32),
+}
+
+pub struct TailCall {
+    pub(crate) func: Value,
+    pub(crate) positional: Vec<Value>,
+    pub(crate) named: Vec<(String, Value)>,
+}
+
+impl Unwind {
+    pub fn error(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Unwind::Throw(Value::Error(Box::new(RuntimeError::new(kind, message))))
+    }
+}
+
+
+/// Globals (functions and builtins) plus one local scope per active call.
+/// Functions see their own locals and the globals, never their caller's locals.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SymbolTable {
+    globals: HashMap<String, Value>,
+    frames: Vec<HashMap<String, Value>>,
+}
+
+impl SymbolTable {
+    fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(name))
+            .or_else(|| self.globals.get(name))
+            .cloned()
+    }
+
+    pub(crate) fn set(&mut self, name: String, value: Value) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(name, value),
+            None => self.globals.insert(name, value),
+        };
+    }
+
+    pub(crate) fn push_frame(&mut self, frame: HashMap<String, Value>) {
+        self.frames.push(frame);
     }
 
-    fn set(&mut self, name: String, value: Value) {
-        self.symbols.insert(name, value);
+    pub(crate) fn pop_frame(&mut self) -> HashMap<String, Value> {
+        self.frames.pop().unwrap_or_default()
     }
 }
 
+/// Calls nested deeper than this raise a `RecursionError` unless changed with
+/// `Evaluator::with_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// When less stack than this is left, `eval` continues on a new segment of
+/// `EVAL_STACK_SIZE` bytes. A call and the loops, `if`s and builtins between it
+/// and the next `eval` fit well within it, even in debug builds. Cloning,
+/// comparing and writing values recurse once per level of nesting too, and
+/// switch stacks the same way, so values nested thousands deep cannot
+/// overflow it.
+const EVAL_RED_ZONE: usize = 1024 * 1024;
+const EVAL_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Runs programs. Evaluation grows its own stack as it nests, so an
+/// `Evaluator` needs no particular thread stack size: recursion is bounded
+/// by `max_depth` alone, and by available memory.
 pub struct Evaluator {
-    symbol_table: SymbolTable, 
-    builtins: Builtins
+    pub(crate) symbol_table: SymbolTable,
+    builtins: Builtins,
+    call_stack: Vec<StackFrame>,
+    max_depth: usize,
+    /// Span of the innermost `Spanned` node being evaluated.
+    location: Option<Span>,
+    /// Command-line arguments for a `main` that takes a parameter.
+    args: Vec<String>,
+    /// The code passed to `exit()`, once it has been called.
+    pub(crate) exit_status: Option<i32>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Evaluator {
     pub fn new() -> Self {
         let mut evaluator = Evaluator {
             symbol_table: SymbolTable::new(),
-            builtins: Builtins::new()
+            builtins: Builtins::new(),
+            call_stack: vec![],
+            max_depth: DEFAULT_MAX_DEPTH,
+            location: None,
+            args: vec![],
+            exit_status: None,
         };
 
         for (name, func) in evaluator.builtins.functions.iter() {
@@ -71,10 +652,47 @@ impl Evaluator {
         evaluator
     }
 
-    pub fn eval(&mut self, node: &AstNode) -> Result<Value, String> {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// The code the program passed to `exit()`, if it called it.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
+    pub(crate) fn eval(&mut self, node: &AstNode) -> Result<Value, Unwind> {
+        // Kept apart from the large `eval_node` frame: nearly every node is
+        // wrapped in `Spanned`, and each call nests dozens of them.
+        stacker::maybe_grow(EVAL_RED_ZONE, EVAL_STACK_SIZE, || {
+            let AstNode::Spanned(span, node) = node else {
+                return self.eval_node(node);
+            };
+            let outer = self.location.replace(*span);
+            let mut result = self.eval_node(node);
+            self.location = outer;
+
+            // Only the innermost span counts, and only until the error leaves the
+            // call it was raised in; outer spans belong to callers.
+            if let Err(Unwind::Throw(Value::Error(error))) = &mut result {
+                if error.location.is_none() && error.stack.is_empty() {
+                    error.location = Some(*span);
+                }
+            }
+            result
+        })
+    }
+
+    fn eval_node(&mut self, node: &AstNode) -> Result<Value, Unwind> {
         match node {
             AstNode::Program(funcdefs) => {
-                let mut result = Value::Integer(0);
+                let mut result = Value::Nil;
 
                 for funcdef in funcdefs {
                     result = self.eval(funcdef)?;
@@ -83,125 +701,164 @@ impl Evaluator {
 
             },
             AstNode::FuncDef { name, args, body } => {
-                if let AstNode::DefArgList(arg_names) = &**args {
-                    let func = Value::Function(name.clone(), arg_names.clone(), body.clone());
+                if let AstNode::DefArgList(params) = &**args {
+                    let (params, body) = (Rc::new(params.clone()), Rc::new((**body).clone()));
+                    let func = if body.contains_yield() {
+                        Value::GeneratorFunction(name.clone(), params, body)
+                    } else {
+                        Value::Function(name.clone(), params, body)
+                    };
                     self.symbol_table.set(name.clone(), func);
 
-                    Ok(Value::Integer(0))
+                    Ok(Value::Nil)
                 } else {
-                    Err("Invalid function definition".to_string())
+                    Err(Unwind::error(ErrorKind::InternalError, "Invalid function definition"))
                 }
             }
             AstNode::Expression(expr) => self.eval(expr),
+            AstNode::BinaryExpression { lhs, op: BinaryOperator::NullCoalesce, rhs } => {
+                match self.eval(lhs)? {
+                    Value::Nil => self.eval(rhs),
+                    value => Ok(value),
+                }
+            },
             AstNode::BinaryExpression { lhs, op, rhs } => {
                 let left = self.eval(lhs)?;
                 let right = self.eval(rhs)?;
-                match (left, right) {
-                    (Value::Integer(l), Value::Integer(r)) => match op {
-                        BinaryOperator::Add => Ok(Value::Integer(l + r)),
-                        BinaryOperator::Subtract => Ok(Value::Integer(l - r)),
-                        BinaryOperator::Multiply => Ok(Value::Integer(l * r)),
-                        BinaryOperator::Divide => Ok(Value::Integer(l / r)),
-                        BinaryOperator::Greater => Ok(Value::Boolean(l > r)),
-                        BinaryOperator::Less => Ok(Value::Boolean(l < r)),
-                        BinaryOperator::Equal => Ok(Value::Boolean(l == r)),
-                        BinaryOperator::NotEqual => Ok(Value::Boolean(l != r)),
-                        BinaryOperator::GreaterEq => Ok(Value::Boolean(l >= r)),
-                        BinaryOperator::LessEq => Ok(Value::Boolean(l <= r)),
-                    },
-                    _ => Err("Invalid operands for binary expression".to_string()),
-
-                }
+                binary_operation(op, left, right)
             },
             AstNode::UnaryExpression { op, child } => {
                 let value = self.eval(child)?;
                 match (op, value) {
-                    (UnaryOperator::Minus, Value::Integer(i)) => Ok(Value::Integer(-i)),
+                    (UnaryOperator::Minus, Value::Integer(i)) => i
+                        .checked_neg()
+                        .map(Value::Integer)
+                        .ok_or_else(|| Unwind::error(ErrorKind::Overflow, format!("Integer overflow in -{}", i))),
                     (UnaryOperator::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
-                    _ => Err("Invalid operand for unary expression".to_string()),
+                    _ => Err(Unwind::error(ErrorKind::TypeMismatch, "Invalid operand for unary expression")),
                 }
             }
             AstNode::Term(term) => self.eval(term),
+            AstNode::List(items) => {
+                let items: Result<Vec<Value>, Unwind> = items.iter().map(|item| self.eval(item)).collect();
+                Ok(Value::new_list(items?))
+            },
+            AstNode::Range { start, end, inclusive, step } => {
+                let start = self.eval(start)?;
+                let end = self.eval(end)?;
+                let step = match step {
+                    Some(step) => self.eval(step)?,
+                    None => Value::Integer(1),
+                };
+                match (start, end, step) {
+                    (_, _, Value::Integer(0)) => Err(Unwind::error(ErrorKind::ValueError, "Range step cannot be zero")),
+                    (Value::Integer(start), Value::Integer(end), Value::Integer(step)) => {
+                        Ok(Value::Range { start, end, step, inclusive: *inclusive })
+                    }
+                    _ => Err(Unwind::error(ErrorKind::TypeMismatch, "Range bounds and step must be integers")),
+                }
+            },
+            AstNode::Index { target, index } => {
+                let target = self.eval(target)?;
+                let index = self.eval(index)?;
+                index_value(&target, &index)
+            },
+            AstNode::Propagate(expr) => match &mut self.eval(expr)? {
+                Value::Ok(value) => Ok(value.take()),
+                err @ Value::Err(_) => Err(Unwind::Return(err.take())),
+                _ => Err(Unwind::error(ErrorKind::TypeMismatch, "The ? operator can only be applied to Ok or Err values")),
+            },
             AstNode::Int(i) => Ok(Value::Integer(*i)),
             AstNode::Boolean(b) => Ok(Value::Boolean(*b)),
             AstNode::Str(s) => Ok(Value::String(s.clone())),
-            AstNode::Identifier(name) => self.symbol_table.get(name).ok_or_else(|| format!("Undefined variable {}", name)),
-            AstNode::Block(statements) => {
-                let mut result = Value::Integer(0);
-
+            AstNode::Nil => Ok(Value::Nil),
+            AstNode::Spanned(..) => self.eval(node),
+            AstNode::Identifier(name) => self.symbol_table.get(name).ok_or_else(|| Unwind::error(ErrorKind::UndefinedVariable, format!("Undefined variable {}", name))),
+            AstNode::Block { statements, tail } => {
                 for stmt in statements {
-                    result = self.eval(stmt)?;
-                    match result {
-                        Value::Integer(0) => { continue }
-                        _ => { break }
-                    }
+                    self.eval(stmt)?;
                 }
 
-                Ok(result)
+                match tail {
+                    Some(tail) => self.eval(tail),
+                    None => Ok(Value::Nil),
+                }
+            },
+            AstNode::FuncReturn(expr) => match expr.unwrap_expression() {
+                AstNode::FuncCall { name, args } => {
+                    let call = self.prepare_call(name, args)?;
+                    match call.func {
+                        Value::Function(..) | Value::GeneratorFunction(..) => Err(Unwind::TailCall(Box::new(call))),
+                        // Builtins like `map` and `next` may call back into cheese code,
+                        // so they run while this call still counts towards `max_depth`.
+                        _ => Err(Unwind::Return(self.call_value_with(&call.func, call.positional, call.named)?)),
+                    }
+                }
+                _ => Err(Unwind::Return(self.eval(expr)?)),
             },
-            AstNode::FuncReturn(expr) => self.eval(expr),
             AstNode::VarDecl { name, value } => {
                 let val = match value {
                     Some(expr) => self.eval(expr)?,
-                    None => Value::Integer(0),
+                    None => Value::Nil,
                 };
 
                 self.symbol_table.set(name.clone(), val);
-                Ok(Value::Integer(0))
-            },
-            AstNode::VarSet { name, value } => {
-                let value = self.eval(value)?;
-                self.symbol_table.set(name.clone(), value);
-                Ok(Value::Integer(0))
+                Ok(Value::Nil)
             },
-            AstNode::FuncCall { name, args } => {
-                // Some(Value::Function(_, params, body))
-                match self.symbol_table.get(name) {
-                    Some(Value::Function(_, params, body)) => {
-                        match &**args {
-                            AstNode::ArgList(arg_values) => {
-                                self.symbol_table = SymbolTable::with_parent(Box::new(self.symbol_table.clone()));
-
-                                for (param, arg) in params.iter().zip(arg_values.iter()) {
-                                    let arg_value = self.eval(arg)?;
-                                    self.symbol_table.set(param.clone(), arg_value);
-                                }
-
-                                let result = self.eval(&body)?;
-                                self.symbol_table = *self.symbol_table.parent.as_mut().unwrap().clone();
-
-                                Ok(result)
-                            },
-                            unknown => panic!("Can only have ArgList as params, you had {:?}", unknown)
+            AstNode::VarSet { target, op, value } => match &**target {
+                AstNode::Identifier(name) => {
+                    let value = match op {
+                        Some(op) => {
+                            let current = self.eval(target)?;
+                            binary_operation(op, current, self.eval(value)?)?
                         }
-                    },
-                    Some(Value::BuiltinFunction(func)) => {
-                        match &**args {
-                            AstNode::ArgList(arg_values) => {
-                                let evaluated_args: Result<Vec<Value>, String> = arg_values
-                                    .iter()
-                                    .map(|arg| self.eval(arg))
-                                    .collect();
-                                func(evaluated_args?)
-                            },
-                            unknown => panic!("Can only have ArgList as params, you had {:?}", unknown)
+                        None => self.eval(value)?,
+                    };
+                    self.symbol_table.set(name.clone(), value);
+                    Ok(Value::Nil)
+                }
+                AstNode::Index { target, index } => {
+                    // Container and index are evaluated exactly once, even for `xs[f()] += 1`.
+                    let container = self.eval(target)?;
+                    let index = self.eval(index)?;
+                    let value = match op {
+                        Some(op) => {
+                            let current = index_value(&container, &index)?;
+                            binary_operation(op, current, self.eval(value)?)?
                         }
-                    }
-                    _ => Err(format!("Function '{}' not found", name))
+                        None => self.eval(value)?,
+                    };
+                    assign_index(&container, index, value)?;
+                    Ok(Value::Nil)
                 }
+                _ => Err(Unwind::error(ErrorKind::InternalError, "Invalid assignment target")),
+            },
+            AstNode::FuncCall { name, args } => {
+                let call = self.prepare_call(name, args)?;
+                self.call_value_with(&call.func, call.positional, call.named)
             },
-            AstNode::IfStatement { condition, body } => {
+            AstNode::IfStatement { condition, body, else_body } => {
                 if let Value::Boolean(true) = self.eval(condition)? {
                     self.eval(body)
+                } else if let Some(else_body) = else_body {
+                    self.eval(else_body)
                 } else {
-                    Ok(Value::Integer(0))
+                    Ok(Value::Nil)
                 }
             },
             AstNode::WhileLoop { condition, body } => {
                 while let Value::Boolean(true) = self.eval(condition)? {
                     self.eval(body)?;
                 } 
-                Ok(Value::Integer(0))
+                Ok(Value::Nil)
+            },
+            AstNode::ForInLoop { names, iterable, body } => {
+                let iter = Iter::from_value(&self.eval(iterable)?)?;
+                while let Some(item) = self.next_item(&iter)? {
+                    self.bind_loop_variables(names, item)?;
+                    self.eval(body)?;
+                }
+                Ok(Value::Nil)
             },
             AstNode::ForLoop { params, body } => {
                 if let AstNode::ForLoopParams { initialization, condition, updater } = &**params {
@@ -211,35 +868,971 @@ impl Evaluator {
                         self.eval(updater)?;
                     }
 
-                    Ok(Value::Integer(0))
+                    Ok(Value::Nil)
                 } else {
-                    Err("Invalid for loop parameters".to_string())
+                    Err(Unwind::error(ErrorKind::InternalError, "Invalid for loop parameters"))
+                }
+            },
+            AstNode::Yield(_) => Err(Unwind::error(ErrorKind::SyntaxError, "yield can only be used as a statement")),
+            AstNode::Throw(expr) => Err(Unwind::Throw(self.eval(expr)?)),
+            AstNode::TryCatch { body, catch, finally } => {
+                // Run pending tail calls here so the body's callee is still covered
+                // by `catch`, and every callee finishes before `finally`.
+                let result = self.eval(body);
+                let result = self.run_tail_call(result);
+                let result = match (result, catch) {
+                    (Err(Unwind::Throw(error)), Some((name, handler))) => {
+                        self.symbol_table.set(name.clone(), error);
+                        let result = self.eval(handler);
+                        self.run_tail_call(result)
+                    }
+                    (result, _) => result,
+                };
+
+                if let Some(finally) = finally {
+                    self.eval(finally)?;
                 }
+                result
             },
-            unknown => panic!("Unimplemented Node {unknown:?}")
+            unknown => Err(Unwind::error(ErrorKind::InternalError, format!("Cannot evaluate {:?}", unknown))),
+        }
+    }
+
+    /// Runs a tail call that `result` defers, as a `return` of its value.
+    fn run_tail_call(&mut self, result: Result<Value, Unwind>) -> Result<Value, Unwind> {
+        match result {
+            Err(Unwind::TailCall(call)) => self
+                .call_value_with(&call.func, call.positional, call.named)
+                .and_then(|value| Err(Unwind::Return(value))),
+            result => result,
         }
     }
 
-    pub fn run(&mut self, ast: &AstNode) -> Result<Value, String> {
-        let _ = self.eval(ast);
+    /// Binds the item produced by a `for-in` loop, destructuring `[a, b]` pairs when the
+    /// loop names several variables (`for i, x in enumerate(xs)`).
+    pub(crate) fn bind_loop_variables(&mut self, names: &[String], item: Value) -> Result<(), Unwind> {
+        if let [name] = names {
+            self.symbol_table.set(name.clone(), item);
+            return Ok(());
+        }
 
-        match self.symbol_table.get("main") {
-            Some(Value::Function(_, params, body)) => {
-                if !params.is_empty() {
-                    return Err("main() function should not have parameters".to_string());
+        match &item {
+            Value::List(items) if items.borrow().len() == names.len() => {
+                for (name, value) in names.iter().zip(items.borrow().iter()) {
+                    self.symbol_table.set(name.clone(), value.clone());
                 }
+                Ok(())
+            }
+            _ => Err(Unwind::error(
+                ErrorKind::TypeMismatch,
+                format!("Cannot unpack {} into {} loop variables", item, names.len()),
+            )),
+        }
+    }
 
-                self.symbol_table = SymbolTable::with_parent(Box::new(self.symbol_table.clone()));
-                let result = self.eval(&body);
-                self.symbol_table = *self.symbol_table.parent.as_mut().unwrap().clone();
+    /// Calls a user-defined or builtin function value with already evaluated arguments.
+    pub fn call_value(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, Unwind> {
+        self.call_value_with(func, args, vec![])
+    }
 
-                result
-            },
-            Some(_) => {
-                panic!("main() is not a function.. how the fuck did you mess that up?")
-            },
-            None => Err("No main() function defined".to_string()),
+    /// Looks up the function called `name` and evaluates its arguments in the
+    /// caller's scope, without calling it yet.
+    fn prepare_call(&mut self, name: &str, args: &AstNode) -> Result<TailCall, Unwind> {
+        let func = self
+            .symbol_table
+            .get(name)
+            .ok_or_else(|| Unwind::error(ErrorKind::UndefinedFunction, format!("Function '{}' not found", name)))?;
+        let AstNode::ArgList(arg_values) = args else {
+            return Err(Unwind::error(ErrorKind::InternalError, "Can only have ArgList as params"));
+        };
+
+        let mut positional = vec![];
+        let mut named = vec![];
+        for arg in arg_values {
+            match &**arg {
+                AstNode::NamedArg { name, value } => named.push((name.clone(), self.eval(value)?)),
+                _ if !named.is_empty() => {
+                    return Err(Unwind::error(
+                        ErrorKind::SyntaxError,
+                        "Positional arguments cannot follow keyword arguments",
+                    ))
+                }
+                _ => positional.push(self.eval(arg)?),
+            }
+        }
+
+        Ok(TailCall { func, positional, named })
+    }
+
+    /// Like `call_value`, additionally passing `name: value` keyword arguments.
+    pub fn call_value_with(
+        &mut self,
+        func: &Value,
+        mut positional: Vec<Value>,
+        mut named: Vec<(String, Value)>,
+    ) -> Result<Value, Unwind> {
+        let mut func = func.clone();
+        loop {
+            let result = match &func {
+                Value::Function(name, params, body) => {
+                    self.enter_call(name)?;
+                    self.symbol_table.push_frame(HashMap::new());
+
+                    let result = match self.bind_parameters(name, params, positional, named) {
+                        Ok(()) => self.call_body(body),
+                        Err(error) => Err(error),
+                    };
+                    self.symbol_table.pop_frame();
+
+                    self.leave_call(result)
+                },
+                Value::GeneratorFunction(name, params, body) => {
+                    self.symbol_table.push_frame(HashMap::new());
+
+                    let bound = self.bind_parameters(name, params, positional, named);
+                    let scope = self.symbol_table.pop_frame();
+                    bound?;
+
+                    let state = GeneratorState::new(name.clone(), scope, body.clone());
+                    return Ok(Iter::Generator(Some(Box::new(state))).into_value());
+                },
+                Value::BuiltinFunction(_) if !named.is_empty() => {
+                    return Err(Unwind::error(
+                        ErrorKind::ArityMismatch,
+                        "Builtin functions do not accept keyword arguments",
+                    ))
+                },
+                Value::BuiltinFunction(func) => return func(self, positional),
+                other => return Err(Unwind::error(ErrorKind::TypeMismatch, format!("{} is not callable", other))),
+            };
+
+            match result {
+                Err(Unwind::TailCall(call)) => {
+                    func = call.func;
+                    positional = call.positional;
+                    named = call.named;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Pushes `name` onto the call stack, refusing to nest deeper than `max_depth`
+    /// so runaway recursion becomes a catchable error instead of a Rust stack overflow.
+    pub(crate) fn enter_call(&mut self, name: &str) -> Result<(), Unwind> {
+        if self.call_stack.len() >= self.max_depth {
+            return Err(Unwind::error(
+                ErrorKind::RecursionError,
+                format!("maximum recursion depth exceeded ({})", self.max_depth),
+            ));
+        }
+        self.call_stack.push(StackFrame {
+            function: name.to_string(),
+            call_site: self.location,
+        });
+        Ok(())
+    }
+
+    /// Pops the frame pushed by `enter_call`. An error leaving its innermost call
+    /// records the stack as it was at that point.
+    pub(crate) fn leave_call<T>(&mut self, mut result: Result<T, Unwind>) -> Result<T, Unwind> {
+        if let Err(Unwind::Throw(Value::Error(error))) = &mut result {
+            if error.stack.is_empty() {
+                error.stack = self.call_stack.clone();
+            }
+        }
+        self.call_stack.pop();
+        result
+    }
+
+    /// Binds call arguments to `params` in the current (fresh) scope: positional
+    /// arguments first, then keyword arguments, then defaults for whatever is left.
+    /// Surplus positional arguments go to the `...rest` parameter as a list.
+    fn bind_parameters(
+        &mut self,
+        function: &str,
+        params: &[Parameter],
+        positional: Vec<Value>,
+        named: Vec<(String, Value)>,
+    ) -> Result<(), Unwind> {
+        let arity_error = |message: String| Err(Unwind::error(ErrorKind::ArityMismatch, format!("{}() {}", function, message)));
+        let fixed: Vec<&Parameter> = params.iter().filter(|param| !param.rest).collect();
+        let rest = params.iter().find(|param| param.rest);
+
+        let given = positional.len();
+        let mut bound: Vec<Option<Value>> = vec![None; fixed.len()];
+        let mut extra = vec![];
+        for (i, value) in positional.into_iter().enumerate() {
+            match bound.get_mut(i) {
+                Some(slot) => *slot = Some(value),
+                None if rest.is_some() => extra.push(value),
+                None => {
+                    return arity_error(format!(
+                        "takes at most {} positional arguments but {} were given",
+                        fixed.len(),
+                        given
+                    ))
+                }
+            }
+        }
+
+        for (name, value) in named {
+            let Some(i) = fixed.iter().position(|param| param.name == name) else {
+                return arity_error(format!("got an unexpected keyword argument '{}'", name));
+            };
+            if bound[i].is_some() {
+                return arity_error(format!("got multiple values for argument '{}'", name));
+            }
+            bound[i] = Some(value);
+        }
+
+        for (param, value) in fixed.iter().zip(bound) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.eval(default)?,
+                (None, None) => return arity_error(format!("missing required argument '{}'", param.name)),
+            };
+            self.symbol_table.set(param.name.clone(), value);
+        }
+        if let Some(rest) = rest {
+            self.symbol_table.set(rest.name.clone(), Value::new_list(extra));
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates a function body, turning a `return` into the call's value. Falling
+    /// off the end yields the body's tail expression, or `None` without one.
+    fn call_body(&mut self, body: &AstNode) -> Result<Value, Unwind> {
+        match self.eval(body) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Evaluates `node` outside any function, so its `let`s and `fn`s become
+    /// globals. The REPL runs each input this way.
+    pub fn eval_global(&mut self, node: &AstNode) -> Result<Value, RuntimeError> {
+        let result = match self.eval(node) {
+            Err(Unwind::TailCall(call)) => self.call_value_with(&call.func, call.positional, call.named),
+            result => result,
+        };
+        finish(result)
+    }
+
+    /// The globals defined by the program, leaving out untouched builtins.
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<_> = self
+            .symbol_table
+            .globals
+            .iter()
+            .filter(|(name, value)| {
+                !(matches!(value, Value::BuiltinFunction(_)) && self.builtins.functions.contains_key(*name))
+            })
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    pub fn run(&mut self, ast: &AstNode) -> Result<Value, RuntimeError> {
+        self.run_entry(ast, "main")
+    }
+
+    /// Defines the functions in `ast` and calls `entry`, passing the arguments
+    /// from `with_args` as a list of strings if it takes a parameter. A call to
+    /// `exit(code)` makes the result `code`, as if `entry` had returned it.
+    pub fn run_entry(&mut self, ast: &AstNode, entry: &str) -> Result<Value, RuntimeError> {
+        finish(self.eval(ast))?;
+
+        let main = self.symbol_table.get(entry);
+        match &main {
+            Some(Value::Function(_, params, _)) if params.len() > 1 => Err(RuntimeError::new(
+                ErrorKind::InvalidMain,
+                format!("{}() takes no parameters, or one for the command-line arguments", entry),
+            )),
+            Some(main @ Value::Function(_, params, _)) => {
+                let args: Vec<Value> = self.args.iter().cloned().map(Value::String).collect();
+                let args = match params.first() {
+                    None => vec![],
+                    // A rest parameter gathers the arguments into a list itself.
+                    Some(param) if param.rest => args,
+                    Some(_) => vec![Value::new_list(args)],
+                };
+                finish(self.call_value(main, args))
+            }
+            Some(Value::GeneratorFunction(..)) => Err(RuntimeError::new(
+                ErrorKind::InvalidMain,
+                format!("{}() cannot be a generator: it must not yield", entry),
+            )),
+            Some(_) => Err(RuntimeError::new(ErrorKind::InvalidMain, format!("{} is not a function", entry))),
+            None => Err(RuntimeError::new(ErrorKind::InvalidMain, format!("No {}() function defined", entry))),
+        }
+    }
+}
+
+/// Turns the outcome of a top-level evaluation into what `run` reports.
+fn finish(result: Result<Value, Unwind>) -> Result<Value, RuntimeError> {
+    match result {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Exit(code)) => Ok(Value::Integer(code)),
+        Err(Unwind::Throw(Value::Error(ref error))) => Err((**error).clone()),
+        Err(Unwind::Throw(value)) => Err(RuntimeError::new(ErrorKind::UncaughtThrow, value.to_string())),
+        Err(Unwind::TailCall(_)) => Err(RuntimeError::new(ErrorKind::InternalError, "Unresolved tail call")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{checker, parser};
+
+    fn run(source: &str) -> Result<Value, RuntimeError> {
+        let ast = parser::parse(source).unwrap();
+        checker::check(&ast).unwrap();
+        Evaluator::new().run(&ast)
+    }
+
+    #[test]
+    fn uninitialized_variables_are_none() {
+        let result = run("fn main() { let tmp; return is_none(tmp); }").unwrap();
+        assert!(matches!(result, Value::Boolean(true)));
+    }
+
+    #[test]
+    fn implicit_return_is_none() {
+        let result = run("fn f() { let a = 1; } fn main() { return f(); }").unwrap();
+        assert!(result.is_nil());
+
+        let result = run("fn f() { return 0; } fn main() { return is_none(f()); }").unwrap();
+        assert!(matches!(result, Value::Boolean(false)));
+    }
+
+    #[test]
+    fn null_coalesce() {
+        let result = run("fn main() { let a; return a ?? 1 + 2; }").unwrap();
+        assert!(matches!(result, Value::Integer(3)));
+
+        let result = run("fn main() { let a = 0; return a ?? missing; }").unwrap();
+        assert!(matches!(result, Value::Integer(0)));
+
+        let result = run("fn main() { return None == None; }").unwrap();
+        assert!(matches!(result, Value::Boolean(true)));
+    }
+
+    #[test]
+    fn binary_operator_precedence() {
+        let result = run("fn main() { return 1 + 2 * 3 == 7; }").unwrap();
+        assert!(matches!(result, Value::Boolean(true)));
+
+        // Operators of one level still associate to the left.
+        let result = run("fn main() { return str(10 - 4 - 3, \" \", 8 / 2 / 2, \" \", 7 % 4 * 2); }").unwrap();
+        assert!(matches!(&result, Value::String(s) if s == "3 2 6"));
+
+        // `??` binds loosest, so its fallback can be a whole comparison.
+        let result = run("fn main() { let x; return x ?? 1 + 1 < 3 == True; }").unwrap();
+        assert!(matches!(result, Value::Boolean(true)));
+    }
+
+    #[test]
+    fn print_returns_none() {
+        assert!(matches!(run("fn main() { return print(); }").unwrap(), Value::Nil));
+    }
+
+    #[test]
+    fn literal_words_are_whole_words() {
+        let result = run("fn main() { let None_x = 1; let Nonexistent = 2; let Trueish = 3; let False_ = 4; \
+            return None_x + Nonexistent + Trueish + False_; }").unwrap();
+        assert!(matches!(result, Value::Integer(10)));
+    }
+
+    #[test]
+    fn catch_runtime_errors() {
+        let source = "fn main() {
+            let kind;
+            try { let x = 1 / 0; } catch e { kind = error_kind(e); };
+            return kind;
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(kind) if kind == "DivisionByZero"));
+
+        let source = "fn main() {
+            try { return missing; } catch e { return error_message(e); };
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(msg) if msg == "Undefined variable missing"));
+    }
+
+    #[test]
+    fn throw_and_finally() {
+        let source = "fn f() {
+            try { throw 42; } finally { print(\"cleanup\"); };
         }
+        fn main() {
+            let order = 0;
+            try { f(); } catch e { order = e; } finally { order = order + 1; };
+            return order;
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(43)));
+    }
+
+    #[test]
+    fn finally_runs_on_return() {
+        let source = "fn main() {
+            let x = 1;
+            try { return x; } finally { x = 2; };
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(1)));
+    }
+
+    #[test]
+    fn tail_calls_finish_before_finally() {
+        let source = "fn note(log, s) { push(log, s); return len(log); }
+        fn attempt(log) {
+            try { throw 1; } catch e { return note(log, \"catch\"); } finally { push(log, \"finally\"); }
+        }
+        fn main() {
+            let log = [];
+            let n = attempt(log);
+            return str(n, \" \", log);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "1 [\"catch\", \"finally\"]"));
+    }
 
+    #[test]
+    fn uncaught_errors() {
+        let error = run("fn main() { throw error(\"Custom\", \"boom\"); }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Custom("Custom".to_string()));
+        assert_eq!(error.message, "boom");
+
+        let error = run("fn main() { return 1 + True; }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    }
+
+    #[test]
+    fn propagate_results() {
+        let source = "fn parse(s) {
+            let n = try_int(s)?;
+            return Ok(n * 2);
+        }
+        fn main() {
+            return str(parse(\"21\"), \" \", is_err(parse(\"abc\")));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "Ok(42) true"));
+
+        let error = run("fn main() { return unwrap(Err(\"bad\")); }").unwrap_err();
+        assert_eq!(error.message, "called unwrap() on Err(bad)");
+    }
+
+    #[test]
+    fn for_in_ranges() {
+        let source = "fn main() {
+            let total = 0;
+            for i in 0..5 { total = total + i; };
+            for i in 1..=3 { total = total + i * 100; };
+            for i in 10..0 step -5 { total = total + i * 1000; };
+            return total;
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(15610)));
+
+        let source = "fn main() {
+            for i in 0..1000000000 { return i; };
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(0)));
+    }
+
+    #[test]
+    fn range_parts_run_in_source_order() {
+        let source = "fn note(log, n) { push(log, n); return n; }
+        fn main() {
+            let log = [];
+            let r = note(log, 1)..note(log, 9) step note(log, 2);
+            return str(log);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "[1, 9, 2]"));
+    }
+
+    #[test]
+    fn for_in_collections() {
+        let source = "fn main() {
+            let out = \"\";
+            for x in [1, 2] { out = str(out, x); };
+            for c in \"ab\" { out = str(out, c); };
+            for k, v in dict(\"x\", 3) { out = str(out, k, v); };
+            for i, x in enumerate([\"p\", \"q\"]) { out = str(out, i, x); };
+            return out;
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "12abx30p1q"));
+    }
+
+    #[test]
+    fn index_collections() {
+        let source = "fn main() {
+            let xs = [1, [2, 3]];
+            push(xs, 4);
+            return xs[1][0] + xs[2] + len(xs) + (dict(\"a\", 1)[\"b\"] ?? 10);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(19)));
+
+        let error = run("fn main() { return [1][1]; }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::IndexError);
+    }
+
+    #[test]
+    fn iterator_adapters() {
+        let source = "fn double(x) { return x * 2; }
+        fn is_even(x) { return x / 2 * 2 == x; }
+        fn main() {
+            let evens = filter(0..1000000000, is_even);
+            let firsts = collect(take(map(evens, double), 3));
+            let tail = collect(skip(chain([1, 2], [3]), 1));
+            let pairs = collect(zip(\"ab\", enumerate([True])));
+            return str(firsts, \" \", tail, \" \", sum(1..=4), \" \", count(\"abc\"), \" \", pairs);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "[0, 4, 8] [2, 3] 10 3 [[\"a\", [0, true]]]"));
+    }
+
+    #[test]
+    fn iterator_protocol() {
+        let source = "fn countdown_next(self) {
+            let n = self[\"n\"];
+            if n == 0 { return Err(\"done\"); };
+            insert(self, \"n\", n - 1);
+            return Ok(n);
+        }
+        fn main() {
+            let it = dict(\"n\", 3, \"next\", countdown_next);
+            let first = next(it);
+            let rest = \"\";
+            for n in it { rest = str(rest, n); };
+            return str(first, \" \", rest, \" \", next(it));
+        }";
+        let expected = "Ok(3) 21 Err(StopIteration: iterator is exhausted)";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == expected));
+
+        // None is an item like any other, from protocol iterators and generators alike.
+        let source = "fn nothing(self) { return Ok(None); }
+        fn nones() { yield None; }
+        fn main() {
+            let it = nones();
+            return str(collect(take(dict(\"next\", nothing), 2)), next(it), next(it));
+        }";
+        let expected = "[None, None]Ok(None)Err(StopIteration: iterator is exhausted)";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == expected));
+        let error = run("fn bad(self) { return 1; } fn main() { return next(dict(\"next\", bad)); }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TypeMismatch);
+    }
+
+    #[test]
+    fn enumerate_past_the_largest_index_overflows() {
+        let Ok(inner) = Iter::from_value(&Value::new_list(vec![Value::Nil, Value::Nil])) else { panic!() };
+        let iter = Rc::new(RefCell::new(Iter::Enumerate { inner, index: i32::MAX - 1 }));
+        let mut evaluator = Evaluator::new();
+        assert!(evaluator.next_item(&iter).is_ok_and(|item| item.is_some()));
+        assert!(matches!(&evaluator.next_item(&iter), Err(Unwind::Throw(Value::Error(error))) if error.kind == ErrorKind::Overflow));
+    }
+
+    #[test]
+    fn generators() {
+        let source = "fn evens(limit) {
+            let n = 0;
+            while n < limit {
+                if n / 2 * 2 == n { yield n; };
+                n = n + 1;
+            };
+            yield 100;
+        }
+        fn pairs() {
+            { yield 0; };
+            for x in [1, 2] {
+                for (let i = 0; i < x; i = i + 1;) { yield x * 10 + i; };
+            };
+            return 0;
+            yield 99;
+        }
+        fn main() {
+            let out = \"\";
+            for n in evens(5) { out = str(out, n, \" \"); };
+            return str(out, collect(pairs()), \" \", sum(take(evens(1000000000), 3)));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "0 2 4 100 [0, 10, 20, 21] 6"));
+    }
+
+    #[test]
+    fn generators_are_lazy() {
+        let source = "fn naturals() {
+            let n = 0;
+            while True { yield n; n = n + 1; };
+        }
+        fn main() {
+            let it = naturals();
+            next(it);
+            return unwrap(next(it)) + unwrap(next(it));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(3)));
+    }
+
+    #[test]
+    fn default_named_and_rest_parameters() {
+        let source = "fn f(a, b = a * 2, ...rest) { return str(a, \" \", b, \" \", rest); }
+        fn main() {
+            return str(f(1), \" | \", f(b: 3, a: 1), \" | \", f(1, 2, 3, 4));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "1 2 [] | 1 3 [] | 1 2 [3, 4]"));
+    }
+
+    #[test]
+    fn strict_arity() {
+        let cases = [
+            ("fn f(a) { return a; } fn main() { f(1, 2); }", "f() takes at most 1 positional arguments but 2 were given"),
+            ("fn f(a, b) { return a; } fn main() { f(1); }", "f() missing required argument 'b'"),
+            ("fn f(a) { return a; } fn main() { f(c: 1); }", "f() got an unexpected keyword argument 'c'"),
+            ("fn f(a) { return a; } fn main() { f(1, a: 1); }", "f() got multiple values for argument 'a'"),
+        ];
+        for (source, message) in cases {
+            let error = run(source).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::ArityMismatch);
+            assert_eq!(error.message, message);
+        }
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let source = "fn main() {
+            let x = 10;
+            x += 5; x -= 1; x *= 3; x /= 2; x %= 8;
+            let xs = [1, [2]];
+            xs[0] += 1;
+            xs[1][0] *= 10;
+            let m = dict();
+            m[\"k\"] = 4;
+            m[\"k\"] -= 1;
+            let total = 0;
+            for (let i = 0; i < 4; i += 1;) { total += i; };
+            return str(x, \" \", xs, \" \", m, \" \", total);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "5 [2, [20]] {\"k\": 3} 6"));
+    }
+
+    #[test]
+    fn aliased_and_cyclic_values() {
+        let source = "fn main() {
+            let m = dict();
+            insert(m, m, 1);
+            insert(m, m, 2);
+            m[m] += 1;
+            let xs = [1];
+            push(xs, xs);
+            let ys = [1];
+            push(ys, ys);
+            return str(len(m), \" \", m[m], \" \", xs, \" \", m, \" \", xs == ys, \" \", xs == [1, xs]);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "1 3 [1, [...]] {{...}: 3} true true"));
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_target_once() {
+        let source = "fn bump(counter) { counter[0] += 1; return 0; }
+        fn main() {
+            let calls = [0];
+            let xs = [5];
+            xs[bump(calls)] += 1;
+            return str(calls[0], xs[0]);
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "16"));
+    }
+
+    #[test]
+    fn expression_statements() {
+        let source = "fn fib(n) { return n; }
+        fn main() {
+            fib(3);
+            1 + 2;
+            [1, 2][0];
+            return 0;
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::Integer(0)));
+    }
+
+    #[test]
+    fn if_and_block_expressions() {
+        let source = "fn sign(n) {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+        fn main() {
+            let c = True;
+            let x = if c { 1 } else { 2 };
+            let y = { let t = 20; t * 2 };
+            let z = c ? 100 : 200;
+            let w = !c ? 1 : c ? 2 : 3;
+            return str(x, \" \", y, \" \", z, \" \", w, \" \", sign(-5), sign(0), sign(7));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "1 40 100 2 -101"));
+    }
+
+    #[test]
+    fn ternary_and_propagate_coexist() {
+        let source = "fn half(n) {
+            let v = try_int(n)?;
+            if v % 2 == 0 ? True : False { Ok(v / 2) } else { Err(\"odd\") }
+        }
+        fn main() { return str(half(\"8\"), half(\"3\"), half(\"x\")); }";
+        let result = run(source).unwrap();
+        assert!(matches!(&result, Value::String(s) if s.starts_with("Ok(4)Err(odd)Err(ValueError")));
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        let source = "fn count(n, acc) {
+            if n == 0 { return acc; }
+            return count(n - 1, acc + 1);
+        }
+        fn is_even(n) { if n == 0 { return True; } return is_odd(n - 1); }
+        fn is_odd(n) { if n == 0 { return False; } return is_even(n - 1); }
+        fn main() { return str(count(1000000, 0), \" \", is_even(1000001)); }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "1000000 false"));
+    }
+
+    #[test]
+    fn tail_calls_inside_try_are_caught() {
+        let source = "fn fail() { throw \"boom\"; }
+        fn f() {
+            let log = [];
+            try { return fail(); } catch e { push(log, e); } finally { push(log, \"finally\"); }
+            return log;
+        }
+        fn main() { return f(); }";
+        assert!(matches!(&run(source).unwrap(), Value::List(log) if log.borrow().len() == 2));
+    }
+
+    #[test]
+    fn functions_do_not_see_caller_locals() {
+        let error = run("fn f() { return secret; } fn main() { let secret = 1; return f(); }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UndefinedVariable);
+    }
+
+    #[test]
+    fn recursion_depth_is_limited() {
+        let source = "fn down(n) { return 1 + down(n + 1); }
+        fn main() { let x = down(0); }";
+        let ast = parser::parse(source).unwrap();
+        let error = Evaluator::new().with_max_depth(10).run(&ast).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::RecursionError);
+        assert_eq!(error.stack.len(), 10);
+        assert_eq!(error.stack[0].function, "main");
+        assert!(error.stack[1..].iter().all(|frame| frame.function == "down"));
+
+        let source = "fn down(n) { return 1 + down(n + 1); }
+        fn main() { try { down(0); } catch e { return error_kind(e); } }";
+        let ast = parser::parse(source).unwrap();
+        let result = Evaluator::new().with_max_depth(10).run(&ast).unwrap();
+        assert!(matches!(&result, Value::String(s) if s == "RecursionError"));
+    }
+
+    #[test]
+    fn default_depth_fits_any_thread_stack() {
+        let bodies = [
+            "for x in [1] { t += down(n + 1); }",
+            "while t == 0 { t = down(n + 1); }",
+            "if True { t = down(n + 1); }",
+            "try { t = down(n + 1); } catch e { throw e; }",
+            "t = sum(map([n + 1], down));",
+            "return sum(map([n + 1], down));",
+            "return collect(map([n + 1], down));",
+            "return next(dict(\"next\", down));",
+        ];
+        for body in bodies {
+            let source =
+                format!("fn down(n) {{ let t = 0; {} return t; }}\nfn main() {{ down(0); }}", body);
+            let ast = parser::parse(&source).unwrap();
+            let error = Evaluator::new().run(&ast).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::RecursionError, "{}", body);
+        }
+    }
+
+    #[test]
+    fn builtin_tail_calls_keep_the_callers_frame() {
+        let source = "fn f(x) { return sum(map([x], f)); }
+        fn main() { try { f(1); } catch e { return error_kind(e); } }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "RecursionError"));
+
+        let source = "fn again(self) { return next(self); }
+        fn main() { return next(dict(\"next\", again)); }";
+        assert_eq!(run(source).unwrap_err().kind, ErrorKind::RecursionError);
+    }
+
+    #[test]
+    fn errors_carry_a_backtrace() {
+        let source = "fn inner() {
+    print(missing);
+}
+fn main() {
+    let x = inner();
+}";
+        let error = run(source).unwrap_err();
+        assert_eq!(error.location.map(|span| (span.line, span.col)), Some((2, 11)));
+        assert_eq!(error.backtrace("test.cheese"), "    at inner() (test.cheese:2:11)\n    at main() (test.cheese:5:13)");
+
+        let source = "fn down(n) { return 1 + down(n + 1); }
+        fn main() { let x = down(0); }";
+        let ast = parser::parse(source).unwrap();
+        let error = Evaluator::new().with_max_depth(10).run(&ast).unwrap_err();
+        assert!(error.backtrace("t").contains("[previous frame repeated 8 more times]"));
+    }
+
+    #[test]
+    fn errors_have_precise_kinds() {
+        let kind = |source: &str| run(source).unwrap_err().kind;
+        assert_eq!(kind("fn main() { return 1 / 0; }"), ErrorKind::DivisionByZero);
+        assert_eq!(kind("fn main() { return 1 + True; }"), ErrorKind::TypeMismatch);
+        assert_eq!(kind("fn main() { return len(1); }"), ErrorKind::TypeMismatch);
+        assert_eq!(kind("fn main() { return len(); }"), ErrorKind::ArityMismatch);
+        assert_eq!(kind("fn main() { return int(\"x\"); }"), ErrorKind::ValueError);
+        assert_eq!(kind("fn main() { return nope(); }"), ErrorKind::UndefinedFunction);
+        assert_eq!(kind("fn main() { throw error(\"TypeMismatch\", \"mine\"); }"), ErrorKind::TypeMismatch);
+        assert_eq!(kind("fn main() { return 2147483647 + 1; }"), ErrorKind::Overflow);
+        assert_eq!(kind("fn main() { let x = 0 - 2147483647 - 1; return -x; }"), ErrorKind::Overflow);
+        assert_eq!(kind("fn main() { let x = 0 - 2147483647 - 1; let m = -1; return x / m; }"), ErrorKind::Overflow);
+        assert_eq!(kind("fn main() { return sum([2147483647, 1]); }"), ErrorKind::Overflow);
+        assert_eq!(kind("fn main(a, b) { }"), ErrorKind::InvalidMain);
+    }
+
+    #[test]
+    fn main_receives_arguments_and_exits() {
+        let ast = parser::parse("fn main(args) { return len(args) * 10 + int(args[1]); }").unwrap();
+        let args = vec!["a".to_string(), "7".to_string()];
+        assert!(matches!(Evaluator::new().with_args(args.clone()).run(&ast), Ok(Value::Integer(27))));
+        let ast = parser::parse("fn main(...args) { return len(args) * 10 + int(args[1]); }").unwrap();
+        assert!(matches!(Evaluator::new().with_args(args).run(&ast), Ok(Value::Integer(27))));
+
+        let source = "fn stop(code) { try { exit(code); } catch e { return 1; } }
+        fn main() { stop(3); return 0; }";
+        let mut evaluator = Evaluator::new();
+        assert!(matches!(evaluator.run(&parser::parse(source).unwrap()), Ok(Value::Integer(3))));
+        assert_eq!(evaluator.exit_status(), Some(3));
+        assert_eq!(run("fn main() { exit(\"x\"); }").unwrap_err().kind, ErrorKind::TypeMismatch);
+        assert_eq!(run("fn main() { exit(256); }").unwrap_err().kind, ErrorKind::ValueError);
+        let error = run("fn main() { yield 1; }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidMain);
+        assert_eq!(error.message, "main() cannot be a generator: it must not yield");
+
+        // Errors while defining the program are reported, not skipped.
+        let ast = parser::parse_repl_input("let x = 1 / 0;").unwrap();
+        assert_eq!(Evaluator::new().run(&ast).unwrap_err().kind, ErrorKind::DivisionByZero);
+    }
+
+    #[test]
+    fn deeply_nested_values() {
+        let source = "fn nest(n) {
+            let xs = [];
+            for i in 0..n { xs = [xs]; }
+            return xs;
+        }
+        fn main() {
+            let xs = nest(50000);
+            let m = dict();
+            for i in 0..50000 { m = dict(\"k\", m); }
+            return str(len(str(xs)), \" \", len(str(m)), \" \", xs == nest(50000), \" \", xs == nest(49999));
+        }";
+        assert!(matches!(&run(source).unwrap(), Value::String(s) if s == "100002 350002 true false"));
+
+        let mut result = Value::Nil;
+        for _ in 0..100000 {
+            result = Value::Err(Box::new(result));
+        }
+        let copy = result.clone();
+        assert!(copy.equals(&result));
+        assert_eq!(result.to_string().len(), 500004);
+    }
+
+    /// A xorshift generator, so the fuzz test below is reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() % n
+        }
+    }
+
+    #[test]
+    fn random_programs_never_panic() {
+        // Loops and input() are left out, so whatever parses also finishes.
+        const TOKENS: &[&str] = &[
+            "fn", "main", "f", "(", ")", "{", "}", "[", "]", ",", ";", "let", "x", "=", "+=", "return", "if", "else",
+            "try", "catch", "e", "finally", "throw", "yield", "0", "1", "2147483647", "99999999999", "-", "+", "*",
+            "/", "%", "==", "<", "!", "??", "..", "..=", "?", ":", "\"s\"", "True", "None", "len", "int", "str",
+            "sum", "error", "unwrap", "dict", "...", " ", "\n",
+        ];
+        const SEEDS: &[&str] = &[
+            "fn main() { let x = [1, 2, 3]; x[0] += 2147483647; return sum(x); }",
+            "fn f(a, b = 2, ...rest) { return a * b + len(rest); } fn main() { return f(1, 2, 3) - f(b = 4, a = 5); }",
+            "fn main() { try { throw error(\"E\", \"m\"); } catch e { return str(e); } finally { } }",
+            "fn main() { let d = dict(\"a\", 1); return d[\"a\"] ?? unwrap(int(\"7\")) % 0; }",
+            "fn g() { yield 1; yield -2147483647 - 1; } fn main() { return sum(g()) + len(str(0..=3)); }",
+        ];
+
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..3000 {
+            let source = match rng.below(2) {
+                0 => (0..1 + rng.below(40)).map(|_| TOKENS[rng.below(TOKENS.len())]).collect::<String>(),
+                _ => {
+                    let mut chars: Vec<char> = SEEDS[rng.below(SEEDS.len())].chars().collect();
+                    for _ in 0..1 + rng.below(3) {
+                        let at = rng.below(chars.len() + 1);
+                        match rng.below(2) {
+                            0 => drop(chars.drain(at..(at + rng.below(8)).min(chars.len()))),
+                            _ => drop(chars.splice(at..at, TOKENS[rng.below(TOKENS.len())].chars())),
+                        }
+                    }
+                    chars.into_iter().collect()
+                }
+            };
+            let Ok(ast) = parser::parse(&source) else { continue };
+            if checker::check(&ast).is_ok() {
+                let _ = Evaluator::new().with_max_depth(10).run(&ast);
+            }
+        }
+
+        let values = [
+            Value::Integer(i32::MIN),
+            Value::Integer(-1),
+            Value::Integer(0),
+            Value::Integer(i32::MAX),
+            Value::String("12x".to_string()),
+            Value::String(String::new()),
+            Value::Boolean(true),
+            Value::Nil,
+            Value::new_list(vec![Value::Integer(i32::MAX), Value::Integer(1)]),
+            Value::new_map(vec![]),
+            Value::Range { start: 0, end: 5, step: -1, inclusive: true },
+        ];
+        // Lists and maps that contain themselves, directly and through each other.
+        let (list, map) = (Value::new_list(vec![]), Value::new_map(vec![]));
+        if let (Value::List(items), Value::Map(entries)) = (&list, &map) {
+            items.borrow_mut().extend([list.clone(), map.clone()]);
+            Value::map_insert(entries, map.clone(), list.clone());
+        }
+        let values: Vec<Value> = values.into_iter().chain([list, map]).collect();
+        let mut evaluator = Evaluator::new();
+        let mut builtins: Vec<_> = Builtins::new().functions.into_iter().filter(|(name, _)| name != "input").collect();
+        builtins.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let values: Vec<Value> = values.into_iter().chain(builtins.iter().map(|(_, f)| Value::BuiltinFunction(*f))).collect();
+        for _ in 0..3000 {
+            let (_, function) = &builtins[rng.below(builtins.len())];
+            let args = (0..rng.below(4)).map(|_| values[rng.below(values.len())].clone()).collect();
+            if let Ok(Value::Iterator(ref iter)) = function(&mut evaluator, args) {
+                for _ in 0..10 {
+                    if !matches!(evaluator.next_item(iter), Ok(Some(_))) {
+                        break;
+                    }
+                }
+            }
+        }
     }
 }
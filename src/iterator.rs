@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::generator::GeneratorState;
+use crate::interpreter::{ErrorKind, Evaluator, Unwind, Value};
+
+/// Lazy iteration state behind `Value::Iterator` and `for-in` loops. Nothing is
+/// materialised up front, so iterating `0..1000000000` costs constant memory.
+#[derive(Debug)]
+pub enum Iter {
+    Range {
+        next: i64,
+        end: i64,
+        step: i64,
+        inclusive: bool,
+    },
+    List {
+        items: Rc<RefCell<Vec<Value>>>,
+        index: usize,
+    },
+    Chars {
+        chars: Vec<char>,
+        index: usize,
+    },
+    Entries {
+        entries: Rc<RefCell<Vec<(Value, Value)>>>,
+        index: usize,
+    },
+    /// A user object implementing the iterator protocol: a map whose `"next"`
+    /// entry is a function taking the map itself and returning `Ok(item)`, or
+    /// any `Err` when done, so that `None` can be an item like any other.
+    Protocol(Value),
+    /// A suspended generator call; `None` while the generator body is running.
+    Generator(Option<Box<GeneratorState>>),
+    Enumerate {
+        inner: Rc<RefCell<Iter>>,
+        index: i32,
+    },
+    Map {
+        inner: Rc<RefCell<Iter>>,
+        func: Value,
+    },
+    Filter {
+        inner: Rc<RefCell<Iter>>,
+        func: Value,
+    },
+    Take {
+        inner: Rc<RefCell<Iter>>,
+        remaining: i32,
+    },
+    Skip {
+        inner: Rc<RefCell<Iter>>,
+        remaining: i32,
+    },
+    Zip {
+        left: Rc<RefCell<Iter>>,
+        right: Rc<RefCell<Iter>>,
+    },
+    Chain {
+        first: Rc<RefCell<Iter>>,
+        second: Rc<RefCell<Iter>>,
+    },
+}
+
+impl Iter {
+    /// Starts iterating `value`. Iterators are shared rather than restarted, so
+    /// a partially consumed iterator picks up where it left off.
+    pub fn from_value(value: &Value) -> Result<Rc<RefCell<Iter>>, Unwind> {
+        let iter = match value {
+            Value::Iterator(iter) => return Ok(iter.clone()),
+            Value::Range { start, end, step, inclusive } => Iter::Range {
+                next: *start as i64,
+                end: *end as i64,
+                step: *step as i64,
+                inclusive: *inclusive,
+            },
+            Value::List(items) => Iter::List {
+                items: items.clone(),
+                index: 0,
+            },
+            Value::String(s) => Iter::Chars {
+                chars: s.chars().collect(),
+                index: 0,
+            },
+            Value::Map(entries) => {
+                let next = Value::map_get(&entries.borrow(), &Value::String("next".to_string()));
+                match next {
+                    Some(Value::Function(..) | Value::BuiltinFunction(_)) => Iter::Protocol(value.clone()),
+                    _ => Iter::Entries {
+                        entries: entries.clone(),
+                        index: 0,
+                    },
+                }
+            }
+            other => return Err(Unwind::error(ErrorKind::TypeMismatch, format!("Cannot iterate over {}", other))),
+        };
+
+        Ok(Rc::new(RefCell::new(iter)))
+    }
+
+    /// Wraps an adapter into a shareable `Value::Iterator`.
+    pub fn into_value(self) -> Value {
+        Value::Iterator(Rc::new(RefCell::new(self)))
+    }
+
+    /// Steps the iterators that produce items on their own, without calling back into cheese.
+    fn advance_source(&mut self) -> Option<Value> {
+        match self {
+            Iter::Range { next, end, step, inclusive } => {
+                let in_range = match (*step > 0, *inclusive) {
+                    (true, true) => *next <= *end,
+                    (true, false) => *next < *end,
+                    (false, true) => *next >= *end,
+                    (false, false) => *next > *end,
+                };
+                if !in_range {
+                    return None;
+                }
+                let value = *next as i32;
+                *next += *step;
+                Some(Value::Integer(value))
+            }
+            Iter::List { items, index } => {
+                let value = items.borrow().get(*index).cloned();
+                *index += 1;
+                value
+            }
+            Iter::Chars { chars, index } => {
+                let value = chars.get(*index).map(|c| Value::String(c.to_string()));
+                *index += 1;
+                value
+            }
+            Iter::Entries { entries, index } => {
+                let value = entries
+                    .borrow()
+                    .get(*index)
+                    .map(|(key, value)| Value::new_list(vec![key.clone(), value.clone()]));
+                *index += 1;
+                value
+            }
+            // Adapters are stepped by `Evaluator::next_item` and never get here.
+            _ => None,
+        }
+    }
+}
+
+/// What `Evaluator::next_item` has to do for one step, copied out of the
+/// `RefCell` so no borrow is held while cheese code runs.
+enum Step {
+    Source,
+    Protocol(Value),
+    Generator(Option<Box<GeneratorState>>),
+    Enumerate(Rc<RefCell<Iter>>, i32),
+    Map(Rc<RefCell<Iter>>, Value),
+    Filter(Rc<RefCell<Iter>>, Value),
+    Take(Rc<RefCell<Iter>>, i32),
+    Skip(Rc<RefCell<Iter>>, i32),
+    Zip(Rc<RefCell<Iter>>, Rc<RefCell<Iter>>),
+    Chain(Rc<RefCell<Iter>>, Rc<RefCell<Iter>>),
+}
+
+impl Evaluator {
+    /// Produces the next item of `iter`, or `None` once it is exhausted.
+    pub fn next_item(&mut self, iter: &Rc<RefCell<Iter>>) -> Result<Option<Value>, Unwind> {
+        let step = match &mut *iter.borrow_mut() {
+            Iter::Protocol(object) => Step::Protocol(object.clone()),
+            Iter::Generator(state) => Step::Generator(state.take()),
+            Iter::Enumerate { inner, index } => {
+                let step = Step::Enumerate(inner.clone(), *index);
+                *index = index
+                    .checked_add(1)
+                    .ok_or_else(|| Unwind::error(ErrorKind::Overflow, "Integer overflow in enumerate()"))?;
+                step
+            }
+            Iter::Map { inner, func } => Step::Map(inner.clone(), func.clone()),
+            Iter::Filter { inner, func } => Step::Filter(inner.clone(), func.clone()),
+            Iter::Take { inner, remaining } => {
+                let step = Step::Take(inner.clone(), *remaining);
+                *remaining = (*remaining - 1).max(0);
+                step
+            }
+            Iter::Skip { inner, remaining } => {
+                let step = Step::Skip(inner.clone(), *remaining);
+                *remaining = 0;
+                step
+            }
+            Iter::Zip { left, right } => Step::Zip(left.clone(), right.clone()),
+            Iter::Chain { first, second } => Step::Chain(first.clone(), second.clone()),
+            _ => Step::Source,
+        };
+
+        match step {
+            Step::Source => Ok(iter.borrow_mut().advance_source()),
+            Step::Protocol(object) => {
+                let next = match &object {
+                    Value::Map(entries) => Value::map_get(&entries.borrow(), &Value::String("next".to_string())),
+                    _ => None,
+                };
+                match next {
+                    Some(next) => match &mut self.call_value(&next, vec![object])? {
+                        Value::Ok(item) => Ok(Some(item.take())),
+                        Value::Err(_) => Ok(None),
+                        other => Err(Unwind::error(
+                            ErrorKind::TypeMismatch,
+                            format!("Iterator next() must return Ok(item) or Err when done, not {}", other),
+                        )),
+                    },
+                    None => Err(Unwind::error(ErrorKind::TypeMismatch, "Iterator object has no next() function")),
+                }
+            }
+            Step::Generator(None) => Err(Unwind::error(ErrorKind::ValueError, "Generator is already running")),
+            Step::Generator(Some(mut state)) => {
+                let result = self.resume_generator(&mut state);
+                *iter.borrow_mut() = Iter::Generator(Some(state));
+                result
+            }
+            Step::Enumerate(inner, index) => Ok(self
+                .next_item(&inner)?
+                .map(|value| Value::new_list(vec![Value::Integer(index), value]))),
+            Step::Map(inner, func) => match self.next_item(&inner)? {
+                Some(value) => Ok(Some(self.call_value(&func, vec![value])?)),
+                None => Ok(None),
+            },
+            Step::Filter(inner, func) => {
+                while let Some(value) = self.next_item(&inner)? {
+                    match self.call_value(&func, vec![value.clone()])? {
+                        Value::Boolean(true) => return Ok(Some(value)),
+                        Value::Boolean(false) => continue,
+                        other => {
+                            return Err(Unwind::error(
+                                ErrorKind::TypeMismatch,
+                                format!("filter() predicate must return a boolean, got {}", other),
+                            ))
+                        }
+                    }
+                }
+                Ok(None)
+            }
+            Step::Take(_, 0) => Ok(None),
+            Step::Take(inner, _) => self.next_item(&inner),
+            Step::Skip(inner, remaining) => {
+                for _ in 0..remaining {
+                    if self.next_item(&inner)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+                self.next_item(&inner)
+            }
+            Step::Zip(left, right) => match (self.next_item(&left)?, self.next_item(&right)?) {
+                (Some(l), Some(r)) => Ok(Some(Value::new_list(vec![l, r]))),
+                _ => Ok(None),
+            },
+            Step::Chain(first, second) => match self.next_item(&first)? {
+                Some(value) => Ok(Some(value)),
+                None => self.next_item(&second),
+            },
+        }
+    }
+}
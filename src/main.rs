@@ -4,26 +4,258 @@ pub mod ast;
 pub mod parser;
 pub mod interpreter;
 pub mod builtins;
+pub mod iterator;
+pub mod generator;
+pub mod checker;
+pub mod diagnostics;
+pub mod codes;
+pub mod repl;
+pub mod cli;
+pub mod runner;
+pub mod formatter;
 
 
 use std::fs;
+use std::io::IsTerminal;
 
-use interpreter::Evaluator;
+use ast::AstNode;
+use cli::{Command, Options, EXIT_IO_ERROR, EXIT_RUNTIME_ERROR, EXIT_SUCCESS, EXIT_SYNTAX_ERROR, EXIT_USAGE};
+use diagnostics::Diagnostic;
+use interpreter::{ErrorKind, Evaluator, RuntimeError, Value};
+
+/// `firstlang explain E0012`: prints the registry entry for an error code.
+fn explain(code: &str) -> i32 {
+    match codes::lookup(code) {
+        Some(entry) => {
+            print!("{}", entry.explain());
+            EXIT_SUCCESS
+        }
+        None => {
+            eprintln!("Unknown error code {}", code);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Points at `explain` for the first of the codes just reported.
+fn suggest_explain(code: Option<&str>) {
+    if let Some(code) = code {
+        eprintln!("For more information about this error, try `firstlang explain {code}`.");
+    }
+}
+
+/// The contents of `path`, or of standard input for `-`.
+fn read_source(path: &str) -> std::io::Result<String> {
+    match path {
+        "-" => std::io::read_to_string(std::io::stdin()),
+        path => fs::read_to_string(path),
+    }
+}
+
+/// Parses and checks `source`, printing every error found.
+fn parse_and_check(file: &str, source: &str, color: bool) -> Option<AstNode> {
+    let ast = match parser::parse(source) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            for error in &errors {
+                eprint!("{}", Diagnostic::from(error).render(file, source, color));
+            }
+            suggest_explain(errors.first().map(|error| error.code()));
+            return None;
+        }
+    };
+    if let Err(errors) = checker::check(&ast) {
+        for error in &errors {
+            eprint!("{}", Diagnostic::from(error).render(file, source, color));
+        }
+        suggest_explain(errors.first().map(|error| error.code()));
+        return None;
+    }
+    Some(ast)
+}
+
+/// `firstlang fmt`: rewrites `path` in the canonical layout, prints it for
+/// standard input, or with `check` only reports whether it would change.
+fn format_source(path: &str, source: &str, ast: &AstNode, check: bool) -> i32 {
+    let formatted = formatter::format_program(source, ast);
+    if check {
+        if formatted == source {
+            return EXIT_SUCCESS;
+        }
+        eprintln!("{path} is not formatted");
+        return EXIT_RUNTIME_ERROR;
+    }
+    if path == "-" {
+        print!("{formatted}");
+    } else if formatted != source {
+        if let Err(e) = fs::write(path, formatted) {
+            eprintln!("{path}: {e}");
+            return EXIT_IO_ERROR;
+        }
+    }
+    EXIT_SUCCESS
+}
+
+fn report_runtime_error(file: &str, source: &str, color: bool, error: &RuntimeError) {
+    eprint!("{}", Diagnostic::from(error).render(file, source, color));
+    eprintln!("{}", error.backtrace(file));
+    suggest_explain(error.kind.code());
+}
+
+/// Runs the command for `options`, returning the exit status.
+fn execute(options: Options) -> i32 {
+    let color = !options.no_color && std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let max_depth = options.max_depth;
+    let path = match options.command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return EXIT_SUCCESS;
+        }
+        Command::Explain(code) => return explain(&code),
+        Command::Repl => {
+            return match repl::run(max_depth, color) {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("{e}");
+                    EXIT_IO_ERROR
+                }
+            };
+        }
+        _ => options.path.unwrap_or_else(|| "-".to_string()),
+    };
+
+    let source = match read_source(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return EXIT_IO_ERROR;
+        }
+    };
+    let file = if path == "-" { "<stdin>".to_string() } else { path.clone() };
+    let Some(ast) = parse_and_check(&file, &source, color) else {
+        return EXIT_SYNTAX_ERROR;
+    };
+
+    match options.command {
+        Command::Check => EXIT_SUCCESS,
+        Command::Ast => {
+            println!("{:#?}", ast);
+            EXIT_SUCCESS
+        }
+        Command::Fmt => format_source(&path, &source, &ast, options.check),
+        Command::Test => {
+            let results = runner::run_tests(&ast, max_depth);
+            let mut failed = 0;
+            for test in &results {
+                match &test.result {
+                    Ok(()) => println!("test {} ... ok", test.name),
+                    Err(error) => {
+                        failed += 1;
+                        println!("test {} ... FAILED", test.name);
+                        report_runtime_error(&file, &source, color, error);
+                    }
+                }
+            }
+            let status = if failed == 0 { "ok" } else { "FAILED" };
+            println!("\ntest result: {}. {} passed; {} failed", status, results.len() - failed, failed);
+            if failed == 0 { EXIT_SUCCESS } else { EXIT_RUNTIME_ERROR }
+        }
+        _ => {
+            let mut evaluator = Evaluator::new().with_max_depth(max_depth).with_args(options.args);
+            match evaluator.run_entry(&ast, &options.entry) {
+                // An integer from main, or passed to exit(), is the exit status.
+                Ok(Value::Integer(status)) if (0..=255).contains(&status) => status,
+                Ok(Value::Integer(status)) => {
+                    let message = format!("{}() returned {}, but an exit status must be 0 to 255", options.entry, status);
+                    report_runtime_error(&file, &source, color, &RuntimeError::new(ErrorKind::ValueError, message));
+                    EXIT_RUNTIME_ERROR
+                }
+                Ok(_) => EXIT_SUCCESS,
+                Err(error) => {
+                    report_runtime_error(&file, &source, color, &error);
+                    EXIT_RUNTIME_ERROR
+                }
+            }
+        }
+    }
+}
+
+/// Runs firstlang with the arguments after the program name, returning the
+/// exit status. Invoked through a `#!` line, the arguments are the script's
+/// path followed by its own arguments.
+fn run_cli(args: impl IntoIterator<Item = String>) -> i32 {
+    match cli::parse_args(args) {
+        Ok(options) => execute(options),
+        Err(message) => {
+            eprintln!("{message}\n\n{}", cli::USAGE);
+            EXIT_USAGE
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        eprintln!("No input file was provided");
-        std::process::exit(-1);
+    std::process::exit(run_cli(std::env::args().skip(1)));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Writes `source` to a scratch file and runs `firstlang <args...>` with the
+    /// file's path in place of `{}`.
+    fn run_script(name: &str, source: &str, args: &[&str]) -> i32 {
+        let path = std::env::temp_dir().join(format!("firstlang-{}-{}.cheese", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        let path = path.to_string_lossy().to_string();
+        let status = run_cli(args.iter().map(|arg| arg.replace("{}", &path)));
+        let _ = fs::remove_file(&path);
+        status
     }
 
-    let source = fs::read_to_string(args[1].clone()).unwrap_or_else(|e| {
-        eprintln!("{e}");
-        std::process::exit(-1);
-    });
+    #[test]
+    fn runs_scripts_with_a_shebang() {
+        let source = "#!/usr/bin/env firstlang\nfn main(args) {\n    return len(args) + int(args[0]);\n}\n";
+        assert_eq!(run_script("shebang", source, &["{}", "40", "--no-color", "x"]), 43);
+        assert_eq!(run_script("shebang-check", source, &["check", "{}"]), EXIT_SUCCESS);
+        // Only the first line may be a shebang.
+        assert_eq!(run_script("late-shebang", "fn main() { }\n#!oops\n", &["{}"]), EXIT_SYNTAX_ERROR);
+    }
 
+    #[test]
+    fn fmt_rewrites_files_and_checks_them() {
+        let path = std::env::temp_dir().join(format!("firstlang-{}-fmt.cheese", std::process::id()));
+        fs::write(&path, "fn main(){print(1+2);}").unwrap();
+        let args = |check: bool| {
+            let path = path.to_string_lossy().to_string();
+            ["fmt".to_string()].into_iter().chain(check.then(|| "--check".to_string())).chain([path])
+        };
+        assert_eq!(run_cli(args(true)), EXIT_RUNTIME_ERROR);
+        assert_eq!(run_cli(args(false)), EXIT_SUCCESS);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {\n    print(1 + 2);\n}\n");
+        assert_eq!(run_cli(args(true)), EXIT_SUCCESS);
+        let _ = fs::remove_file(&path);
+    }
 
-    let ast = parser::parse(&source).unwrap_or_else(|e| panic!("{e}"));
-    let mut evaluator = Evaluator::new();
-    evaluator.run(&ast).unwrap();
+    #[test]
+    fn exit_status_reflects_the_outcome() {
+        let run = |name, source| run_script(name, source, &["--no-color", "{}"]);
+        assert_eq!(run("nothing", "fn main() { }"), EXIT_SUCCESS);
+        assert_eq!(run("string", "fn main() { return \"done\"; }"), EXIT_SUCCESS);
+        assert_eq!(run("returns", "fn main() { return 42; }"), 42);
+        assert_eq!(run("out_of_range", "fn main() { return 256; }"), EXIT_RUNTIME_ERROR);
+        assert_eq!(run("negative", "fn main() { return -1; }"), EXIT_RUNTIME_ERROR);
+        assert_eq!(run("largest", "fn main() { return 255; }"), 255);
+        assert_eq!(run("exits", "fn f() { exit(9); } fn main() { f(); return 1; }"), 9);
+        assert_eq!(run("runtime", "fn main() { return 1 / 0; }"), EXIT_RUNTIME_ERROR);
+        assert_eq!(run("syntax", "fn main() { return 1 +; }"), EXIT_SYNTAX_ERROR);
+        assert_eq!(run("check", "fn main(a, a) { }"), EXIT_SYNTAX_ERROR);
+        assert_eq!(run_script("test", "fn test_fails() { throw 1; }", &["test", "{}"]), EXIT_RUNTIME_ERROR);
+        assert_eq!(run_script("unformatted", "fn main(){ }", &["fmt", "--check", "{}"]), EXIT_RUNTIME_ERROR);
+        assert_eq!(run_cli(["/no/such/file.cheese".to_string()]), EXIT_IO_ERROR);
+        assert_eq!(run_cli(["--bogus".to_string()]), EXIT_USAGE);
+        assert_eq!(run_cli(["-v".to_string(), "f.cheese".to_string()]), EXIT_USAGE);
+        // A program's own status is passed through even where it means an error.
+        assert_eq!(run("exits_like_syntax", "fn main() { exit(3); }"), EXIT_SYNTAX_ERROR);
+        assert_eq!(run("returns_like_io", "fn main() { return 4; }"), EXIT_IO_ERROR);
+    }
 }
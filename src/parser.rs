@@ -1,61 +1,532 @@
+use std::fmt;
+use std::ops::Range;
 use std::result::Result;
 
-use pest::iterators::Pair;
+use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 
-use crate::ast::{AstNode, BinaryOperator, UnaryOperator};
+use crate::ast::{AstNode, BinaryOperator, Parameter, Span, UnaryOperator};
 
 #[derive(pest_derive::Parser)]
 #[grammar = "grammar.pest"]
 struct CalcParser;
 
-pub fn parse(source: &str) -> Result<AstNode, pest::error::Error<Rule>> {
-    let pair = CalcParser::parse(Rule::Program, source)?.next().unwrap();
-    let ast = build_ast_from_root(pair);
+/// What the parser wanted to see where a `ParseError` occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A statement ran into the next one without a `;`.
+    MissingSemicolon,
+    /// An argument or list item not followed by `,` or the closing bracket.
+    MissingSeparator { closing: char },
+    /// The file ended before a bracket was closed.
+    UnclosedDelimiter { closing: char },
+    /// Anything else, described in words.
+    Expected(String),
+    /// A number literal outside the range of a 32-bit integer.
+    IntegerTooLarge,
+    /// The grammar accepted something the AST builders do not handle.
+    Internal(String),
+    /// Brackets, `?:` or `else if` nested past `MAX_NESTING` levels, or a
+    /// syntax tree deeper than the interpreter will walk.
+    TooDeeplyNested,
+}
+
+/// A syntax error and the source it points at.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// The offending token, or `None` at the end of the file.
+    pub found: Option<String>,
+    pub span: Span,
+}
+
+impl ParseError {
+    /// The stable code `firstlang explain` knows this error by.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            ParseErrorKind::MissingSemicolon => "E0001",
+            ParseErrorKind::MissingSeparator { .. } => "E0002",
+            ParseErrorKind::UnclosedDelimiter { .. } => "E0003",
+            ParseErrorKind::Expected(_) => "E0004",
+            ParseErrorKind::IntegerTooLarge => "E0005",
+            ParseErrorKind::Internal(_) => "E0006",
+            ParseErrorKind::TooDeeplyNested => "E0026",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let expected = match &self.kind {
+            ParseErrorKind::MissingSemicolon => "`;` after statement".to_string(),
+            ParseErrorKind::MissingSeparator { closing } => format!("`,` or `{}`", closing),
+            ParseErrorKind::UnclosedDelimiter { closing } => format!("`{}`", closing),
+            ParseErrorKind::Expected(expected) => expected.clone(),
+            ParseErrorKind::IntegerTooLarge => {
+                return format!("integer literal `{}` is too large", self.found.as_deref().unwrap_or_default())
+            }
+            ParseErrorKind::Internal(message) => return format!("internal parser error: {}", message),
+            ParseErrorKind::TooDeeplyNested => return "code is nested too deeply".to_string(),
+        };
+        match &self.found {
+            Some(found) => format!("expected {}, found `{}`", expected, found),
+            None => format!("expected {}, found end of file", expected),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.col, self.message())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a whole program. On failure every syntax error in the file is
+/// reported, not just the first: after each error the offending statement (or,
+/// outside any block, function) is blanked out and parsing starts over.
+pub fn parse(source: &str) -> Result<AstNode, Vec<ParseError>> {
+    parse_rule(source, Rule::Program, build_ast_from_root)
+}
+
+/// Parses one entry typed at the REPL into a `Block`: function definitions and
+/// statements, then the expression to echo as its tail.
+pub fn parse_repl_input(source: &str) -> Result<AstNode, Vec<ParseError>> {
+    parse_rule(source, Rule::ReplInput, build_ast_from_repl_input)
+}
+
+/// How deeply brackets, `?:` and `else if` chains may nest. The parser
+/// recurses once per level, so the limit is what keeps it on its stack.
+pub const MAX_NESTING: usize = 200;
+
+/// How deep a syntax tree may be, for the checker and interpreter that walk
+/// it recursively. Long chains of binary operators build deep trees too.
+const MAX_TREE_DEPTH: usize = 1000;
+
+/// Stack the parser needs per level of nesting, with room to spare in debug
+/// builds. Parsing continues on a new stack of `PARSE_STACK_SIZE` bytes only
+/// when less than this is left for each level the source nests, so shallow
+/// inputs like most REPL entries parse where they are.
+const PARSE_STACK_PER_LEVEL: usize = 64 * 1024;
+const PARSE_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn parse_rule(source: &str, rule: Rule, build: fn(Pair<Rule>) -> Built) -> Result<AstNode, Vec<ParseError>> {
+    let depth = nesting_depth(source).map_err(|pos| vec![too_deeply_nested(source, pos)])?;
+    let red_zone = (depth + 1) * PARSE_STACK_PER_LEVEL;
+    let ast = stacker::maybe_grow(red_zone, PARSE_STACK_SIZE, || parse_with_recovery(source, rule, build))?;
+    match deeper_than(&ast, MAX_TREE_DEPTH, None) {
+        Some(span) => Err(vec![ParseError { kind: ParseErrorKind::TooDeeplyNested, found: None, span }]),
+        None => Ok(ast),
+    }
+}
+
+fn parse_with_recovery(source: &str, rule: Rule, build: fn(Pair<Rule>) -> Built) -> Result<AstNode, Vec<ParseError>> {
+    let mut errors = vec![];
+    let mut text = source.to_string();
+    loop {
+        let error = match CalcParser::parse(rule, &text) {
+            Ok(_) if !errors.is_empty() => return Err(errors),
+            Ok(mut pairs) => {
+                let root = match pairs.next() {
+                    Some(root) => build(root),
+                    None => Err(internal_error(Span { start: 0, end: 0, line: 1, col: 1 }, "no program")),
+                };
+                return root.map_err(|error| vec![error]);
+            }
+            Err(error) => error,
+        };
+        let pos = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        errors.push(describe_error(&text, pos, &error.variant));
+
+        let skipped = recovery_region(&text, pos);
+        if text[skipped.clone()].trim().is_empty() {
+            return Err(errors);
+        }
+        // Blank rather than remove, so later errors keep their positions.
+        let blanked: String = text[skipped.clone()]
+            .bytes()
+            .map(|byte| if byte == b'\n' { '\n' } else { ' ' })
+            .collect();
+        text.replace_range(skipped, &blanked);
+    }
+}
+
+/// How deeply `text` nests, or where it first nests deeper than `MAX_NESTING`.
+/// Brackets nest, and so does each `?` or `else`, until the end of the
+/// statement, argument or `if` chain it is part of.
+fn nesting_depth(text: &str) -> Result<usize, usize> {
+    let code: Vec<(usize, u8)> = code_bytes(text).filter(|(_, byte)| !byte.is_ascii_whitespace()).collect();
+    // The `?` and `else` chained so far inside each open bracket; the first
+    // entry is for the top level, so there is always a last one.
+    let mut chained = vec![0];
+    let mut depth = 0;
+    let mut deepest = 0;
+    for (n, &(i, byte)) in code.iter().enumerate() {
+        let next = code.get(n + 1).copied();
+        let innermost = chained.len() - 1;
+        match byte {
+            b'(' | b'[' | b'{' => {
+                chained.push(0);
+                depth += 1;
+            }
+            b')' | b']' | b'}' if innermost > 0 => {
+                depth -= 1 + chained.pop().unwrap_or_default();
+                // An `if` chain ends at a `}` that is not followed by `else`.
+                if byte == b'}' && !next.is_some_and(|(j, _)| is_keyword(text, j, "else")) {
+                    depth -= std::mem::take(&mut chained[innermost - 1]);
+                }
+            }
+            b';' | b',' => depth -= std::mem::take(&mut chained[innermost]),
+            b'?' if next.map(|(_, next)| next) != Some(b'?') && (n == 0 || code[n - 1].1 != b'?') => {
+                chained[innermost] += 1;
+                depth += 1;
+            }
+            b'e' if is_keyword(text, i, "else") => {
+                chained[innermost] += 1;
+                depth += 1;
+            }
+            _ => {}
+        }
+        if depth > MAX_NESTING {
+            return Err(i);
+        }
+        deepest = deepest.max(depth);
+    }
+    Ok(deepest)
+}
+
+fn too_deeply_nested(text: &str, pos: usize) -> ParseError {
+    let (line, col) = line_col(text, pos);
+    ParseError {
+        kind: ParseErrorKind::TooDeeplyNested,
+        found: None,
+        span: Span { start: pos, end: pos + 1, line, col },
+    }
+}
+
+/// The span around the first node more than `limit` levels below `node`,
+/// not counting `Spanned` wrappers.
+fn deeper_than(node: &AstNode, limit: usize, span: Option<Span>) -> Option<Span> {
+    if let AstNode::Spanned(span, inner) = node {
+        return deeper_than(inner, limit, Some(*span));
+    }
+    if limit == 0 {
+        return span.or(Some(Span { start: 0, end: 0, line: 1, col: 1 }));
+    }
+    node.children().into_iter().find_map(|child| deeper_than(child, limit - 1, span))
+}
+
+/// Turns pest's list of expected rules at `pos` into a readable message.
+fn describe_error(text: &str, pos: usize, variant: &pest::error::ErrorVariant<Rule>) -> ParseError {
+    let token_len = match text[pos..].chars().next() {
+        None => 0,
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => text[pos..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len() - pos),
+        Some(c) => c.len_utf8(),
+    };
+    let found = Some(&text[pos..pos + token_len]).filter(|token| !token.is_empty());
+
+    let positives = match variant {
+        pest::error::ErrorVariant::ParsingError { positives, .. } => positives.as_slice(),
+        pest::error::ErrorVariant::CustomError { .. } => &[],
+    };
+    let continues_expression = |rule: &Rule| {
+        matches!(
+            rule,
+            Rule::BinaryOperator
+                | Rule::RangeOperator
+                | Rule::Propagate
+                | Rule::Index
+                | Rule::Ternary
+                | Rule::AssignOperator
+        )
+    };
+
+    let kind = match (unclosed_bracket(&text[..pos]), found) {
+        (Some(bracket), None) => ParseErrorKind::UnclosedDelimiter { closing: closing(bracket) },
+        // Everything before `pos` parsed as a complete expression, so the real
+        // problem is a missing separator.
+        (bracket, _) if !positives.is_empty() && positives.iter().all(continues_expression) => match bracket {
+            Some(bracket @ ('(' | '[')) => ParseErrorKind::MissingSeparator { closing: closing(bracket) },
+            _ => ParseErrorKind::MissingSemicolon,
+        },
+        _ => {
+            let mut descriptions: Vec<&str> = vec![];
+            for rule in positives {
+                let description = describe_rule(rule);
+                if !descriptions.contains(&description) {
+                    descriptions.push(description);
+                }
+            }
+            // A name is itself an expression.
+            if descriptions.contains(&"an expression") {
+                descriptions.retain(|description| *description != "a name");
+            }
+            ParseErrorKind::Expected(match descriptions.split_last() {
+                None => "something else".to_string(),
+                Some((last, [])) => last.to_string(),
+                Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            })
+        }
+    };
+
+    let (line, col) = line_col(text, pos);
+    ParseError {
+        kind,
+        found: found.map(str::to_string),
+        span: Span { start: pos, end: pos + token_len, line, col },
+    }
+}
+
+fn describe_rule(rule: &Rule) -> &'static str {
+    match rule {
+        // `EOI` is only ever expected where another function could start.
+        Rule::Program | Rule::FuncDef | Rule::EOI => "a function definition",
+        Rule::ReplInput => "a statement",
+        Rule::Statement => "a statement",
+        Rule::Block => "`{`",
+        Rule::Identifier => "a name",
+        // pest reports a malformed list at its first parameter.
+        Rule::Parameter | Rule::DefArgList | Rule::RestMarker => "parameters like `(a, b = 1, ...rest)`",
+        Rule::ArgList | Rule::NamedArgument => "an argument",
+        Rule::BinaryOperator => "an operator",
+        Rule::AssignOperator => "`=`",
+        Rule::ForParams => "`(`",
+        Rule::CatchClause => "`catch`",
+        Rule::FinallyClause => "`finally`",
+        _ => "an expression",
+    }
+}
 
-    Ok(ast)
+/// The byte ranges of the `//` comments in `text`, each up to the end of its
+/// line. A shebang line is not a comment.
+pub fn comments(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut comments = vec![];
+    let mut in_string = false;
+    let mut i = if text.starts_with("#!") { text.find('\n').unwrap_or(text.len()) } else { 0 };
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => in_string = !in_string,
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'/') => {
+                let end = text[i..].find('\n').map_or(text.len(), |n| i + n);
+                comments.push(i..end);
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    comments
+}
+
+/// Bytes of `text` that are source code rather than the inside of a string
+/// literal or a comment.
+fn code_bytes(text: &str) -> impl Iterator<Item = (usize, u8)> + '_ {
+    let mut comments = comments(text).into_iter().peekable();
+    let mut in_string = false;
+    text.bytes().enumerate().filter(move |&(i, byte)| {
+        while comments.next_if(|comment| comment.end <= i).is_some() {}
+        if comments.peek().is_some_and(|comment| comment.contains(&i)) {
+            return false;
+        }
+        if byte == b'"' {
+            in_string = !in_string;
+            return false;
+        }
+        !in_string
+    })
 }
 
-fn build_ast_from_root(pair: Pair<Rule>) -> AstNode {
+/// The innermost bracket opened in `text` and not closed again.
+fn unclosed_bracket(text: &str) -> Option<char> {
+    let mut open = vec![];
+    for (_, byte) in code_bytes(text) {
+        match byte {
+            b'(' | b'[' | b'{' => open.push(byte as char),
+            b')' | b']' | b'}' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.pop()
+}
+
+fn closing(bracket: char) -> char {
+    match bracket {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// The source to skip after a syntax error at `pos`: inside a block, the
+/// statement containing `pos`; at the top level, everything up to the next `fn`.
+fn recovery_region(text: &str, pos: usize) -> Range<usize> {
+    let mut depth = 0;
+    let mut statement_start = 0;
+    let mut item_start = 0;
+    for (i, byte) in code_bytes(&text[..pos]) {
+        match byte {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        if matches!(byte, b'{' | b'}' | b';') {
+            statement_start = i + 1;
+        }
+        if byte == b'}' && depth == 0 {
+            item_start = i + 1;
+        }
+    }
+
+    if depth <= 0 {
+        let last_fn = code_bytes(&text[..pos]).filter(|&(i, byte)| byte == b'f' && is_fn_keyword(text, i)).last();
+        let item_start = item_start.max(last_fn.map_or(0, |(i, _)| i));
+        let mut depth = 0;
+        for (i, byte) in code_bytes(text).skip_while(|&(i, _)| i <= pos) {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => depth -= 1,
+                b'f' if depth <= 0 && is_fn_keyword(text, i) => return item_start..i,
+                _ => {}
+            }
+        }
+        return item_start..text.len();
+    }
+
+    let mut depth = 0;
+    for (i, byte) in code_bytes(text).skip_while(|&(i, _)| i < pos) {
+        match byte {
+            b';' if depth == 0 => return statement_start..i + 1,
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return statement_start..i,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return statement_start..i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    statement_start..text.len()
+}
+
+fn is_fn_keyword(text: &str, i: usize) -> bool {
+    is_keyword(text, i, "fn")
+}
+
+/// Whether `keyword` starts at `i` as a whole word.
+fn is_keyword(text: &str, i: usize, keyword: &str) -> bool {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text[i..].starts_with(keyword)
+        && !text[..i].ends_with(word)
+        && !text[i + keyword.len()..].starts_with(word)
+}
+
+type Built<T = AstNode> = Result<T, ParseError>;
+
+/// The next child of `pairs`, which the grammar guarantees exists under `parent`.
+fn child<'a>(pairs: &mut Pairs<'a, Rule>, parent: Span) -> Built<Pair<'a, Rule>> {
+    pairs.next().ok_or_else(|| internal_error(parent, "a rule is missing a child"))
+}
+
+/// A rule a builder was not written for, i.e. the grammar and the builders disagree.
+fn unexpected(pair: &Pair<Rule>) -> ParseError {
+    internal_error(span_of(pair), format!("unexpected {:?}", pair.as_rule()))
+}
+
+fn internal_error(span: Span, message: impl Into<String>) -> ParseError {
+    ParseError {
+        kind: ParseErrorKind::Internal(message.into()),
+        found: None,
+        span,
+    }
+}
+
+fn build_ast_from_root(pair: Pair<Rule>) -> Built {
     let mut func_defs: Vec<Box<AstNode>> = vec![];
     let rule_pairs = pair.into_inner();
     for rule_pair in rule_pairs {
         match rule_pair.as_rule() {
-            Rule::FuncDef => func_defs.push(Box::new(parse_func_def(rule_pair))),
+            Rule::FuncDef => func_defs.push(Box::new(parse_func_def(rule_pair)?)),
             Rule::EOI => (),
-            unknown => panic!("Unknown root: {:?}", unknown),
+            _ => return Err(unexpected(&rule_pair)),
         };
     }
 
-    AstNode::Program(func_defs)
+    Ok(AstNode::Program(func_defs))
 }
 
-fn build_ast_from_block(pair: Pair<Rule>) -> AstNode {
-    match pair.as_rule() {
+fn build_ast_from_repl_input(pair: Pair<Rule>) -> Built {
+    let mut statements = vec![];
+    let mut tail = None;
+    for item in pair.into_inner() {
+        match item.as_rule() {
+            Rule::FuncDef => statements.push(Box::new(parse_func_def(item)?)),
+            Rule::Statement => statements.push(Box::new(build_ast_from_statement(item)?)),
+            Rule::Expression => tail = Some(Box::new(build_ast_from_expression(item)?)),
+            Rule::EOI => (),
+            _ => return Err(unexpected(&item)),
+        }
+    }
+    Ok(AstNode::Block { statements, tail })
+}
+
+fn build_ast_from_block(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
+    let node = match pair.as_rule() {
         Rule::Block => {
             let mut statement_vec: Vec<Box<AstNode>> = vec![];
+            let mut tail = None;
             let statements = pair.into_inner();
+            let mut open_ended = false;
             for statement in statements {
-                statement_vec.push(Box::new(build_ast_from_statement(statement)));
+                match statement.as_rule() {
+                    Rule::Expression => tail = Some(Box::new(build_ast_from_expression(statement)?)),
+                    _ => {
+                        open_ended = !statement.as_str().trim_end().ends_with(';');
+                        statement_vec.push(Box::new(build_ast_from_statement(statement)?));
+                    }
+                }
+            }
+            // Like a tail expression, a trailing `if`/`else` or `{ }` without a `;`
+            // gives the block its value.
+            if tail.is_none()
+                && open_ended
+                && matches!(
+                    statement_vec.last().map(|s| s.unspanned()),
+                    Some(AstNode::IfStatement { .. } | AstNode::Block { .. })
+                )
+            {
+                tail = statement_vec.pop();
             }
 
-            AstNode::Block(statement_vec)
+            AstNode::Block {
+                statements: statement_vec,
+                tail,
+            }
         }
-        unknown => panic!("Unknown block: {:?}", unknown),
-    }
+        _ => return Err(unexpected(&pair)),
+    };
+    Ok(with_span(span, node))
 }
 
-fn build_ast_from_statement(pair: Pair<Rule>) -> AstNode {
-    let pair = pair.into_inner().next().unwrap();
-    match pair.as_rule() {
+fn build_ast_from_statement(pair: Pair<Rule>) -> Built {
+    let statement_span = span_of(&pair);
+    let pair = child(&mut pair.into_inner(), statement_span)?;
+    let span = span_of(&pair);
+    let node = match pair.as_rule() {
         Rule::VarDecl => {
             let mut pair = pair.into_inner();
-            let name = pair.next().unwrap().as_str();
-            let value = pair.next();
-            let value = match value {
-                Some(pair) => Some(Box::new(build_ast_from_expression(pair))),
-                None => None,
-            };
+            let name = child(&mut pair, span)?.as_str();
+            let value = pair.next().map(build_ast_from_expression).transpose()?.map(Box::new);
             AstNode::VarDecl {
                 name: name.to_string(),
                 value,
@@ -63,216 +534,646 @@ fn build_ast_from_statement(pair: Pair<Rule>) -> AstNode {
         }
         Rule::VarSet => {
             let mut pair = pair.into_inner();
-            let name = pair.next().unwrap().as_str();
-            let value = pair.next();
-            let value = match value {
-                Some(pair) => Some(Box::new(build_ast_from_expression(pair))),
-                None => None,
+            let target = build_ast_from_assign_target(child(&mut pair, span)?)?;
+            let op = child(&mut pair, span)?;
+            let op = match op.as_str() {
+                "=" => None,
+                compound => Some(parse_binary_operator_str(compound.trim_end_matches('='), span_of(&op))?),
             };
+            let value = build_ast_from_expression(child(&mut pair, span)?)?;
             AstNode::VarSet {
-                name: name.to_string(),
-                value: value.unwrap(),
+                target: Box::new(target),
+                op,
+                value: Box::new(value),
             }
         }
-        Rule::FuncCall => build_ast_from_function_call(pair),
-        Rule::FuncReturn => AstNode::FuncReturn(Box::new(build_ast_from_expression(
-            pair.into_inner().next().unwrap(),
-        ))),
+        Rule::FuncCall => build_ast_from_function_call(pair)?,
+        Rule::FuncReturn => AstNode::FuncReturn(Box::new(build_ast_from_expression(child(
+            &mut pair.into_inner(),
+            span,
+        )?)?)),
         Rule::ForLoop => {
             let mut pair = pair.into_inner();
-            let for_params = parse_for_params(pair.next().unwrap());
-            let block = build_ast_from_block(pair.next().unwrap());
+            let for_params = parse_for_params(child(&mut pair, span)?)?;
+            let block = build_ast_from_block(child(&mut pair, span)?)?;
             AstNode::ForLoop {
                 params: Box::new(for_params),
                 body: Box::new(block),
             }
         }
+        Rule::ForInLoop => {
+            let mut names = vec![];
+            let mut iterable = None;
+            let mut body = None;
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::Identifier => names.push(pair.as_str().to_string()),
+                    Rule::Expression => iterable = Some(build_ast_from_expression(pair)?),
+                    Rule::Block => body = Some(build_ast_from_block(pair)?),
+                    _ => return Err(unexpected(&pair)),
+                }
+            }
+            let missing = || internal_error(span, "a for loop is missing a part");
+            AstNode::ForInLoop {
+                names,
+                iterable: Box::new(iterable.ok_or_else(missing)?),
+                body: Box::new(body.ok_or_else(missing)?),
+            }
+        }
         Rule::WhileLoop => {
             let mut pair = pair.into_inner();
-            let condition = build_ast_from_expression(pair.next().unwrap());
-            let block = build_ast_from_block(pair.next().unwrap());
+            let condition = build_ast_from_expression(child(&mut pair, span)?)?;
+            let block = build_ast_from_block(child(&mut pair, span)?)?;
             AstNode::WhileLoop {
                 condition: Box::new(condition),
                 body: Box::new(block),
             }
         }
-        Rule::IfStatement => {
+        Rule::IfStatement => build_ast_from_if(pair)?,
+        Rule::Block => build_ast_from_block(pair)?,
+        Rule::Expression => build_ast_from_expression(pair)?,
+        Rule::Yield => AstNode::Yield(Box::new(build_ast_from_expression(child(
+            &mut pair.into_inner(),
+            span,
+        )?)?)),
+        Rule::Throw => AstNode::Throw(Box::new(build_ast_from_expression(child(
+            &mut pair.into_inner(),
+            span,
+        )?)?)),
+        Rule::TryCatch => {
             let mut pair = pair.into_inner();
-            let condition = build_ast_from_expression(pair.next().unwrap());
-            let block = build_ast_from_block(pair.next().unwrap());
-            AstNode::IfStatement {
-                condition: Box::new(condition),
-                body: Box::new(block),
+            let body = build_ast_from_block(child(&mut pair, span)?)?;
+            let mut catch = None;
+            let mut finally = None;
+            for clause in pair {
+                let clause_span = span_of(&clause);
+                match clause.as_rule() {
+                    Rule::CatchClause => {
+                        let mut clause = clause.into_inner();
+                        let name = child(&mut clause, clause_span)?.as_str().to_string();
+                        let handler = build_ast_from_block(child(&mut clause, clause_span)?)?;
+                        catch = Some((name, Box::new(handler)));
+                    }
+                    Rule::FinallyClause => {
+                        finally = Some(Box::new(build_ast_from_block(child(
+                            &mut clause.into_inner(),
+                            clause_span,
+                        )?)?));
+                    }
+                    _ => return Err(unexpected(&clause)),
+                }
+            }
+            AstNode::TryCatch {
+                body: Box::new(body),
+                catch,
+                finally,
             }
         }
-        unknown => panic!("Unknown statement: {:?}", unknown),
-    }
+        _ => return Err(unexpected(&pair)),
+    };
+    Ok(with_span(span, node))
 }
 
-fn build_ast_from_expression(pair: Pair<Rule>) -> AstNode {
-    match pair.as_rule() {
-        Rule::Expression => AstNode::Expression(Box::new(build_ast_from_expression(
-            pair.into_inner().next().unwrap(),
-        ))),
-        Rule::Number => parse_number(pair),
+fn build_ast_from_if(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
+    let mut pair = pair.into_inner();
+    let condition = build_ast_from_expression(child(&mut pair, span)?)?;
+    let block = build_ast_from_block(child(&mut pair, span)?)?;
+    let else_body = match pair.next() {
+        Some(else_body) if else_body.as_rule() == Rule::IfStatement => Some(Box::new(build_ast_from_if(else_body)?)),
+        Some(else_body) => Some(Box::new(build_ast_from_block(else_body)?)),
+        None => None,
+    };
+    let node = AstNode::IfStatement {
+        condition: Box::new(condition),
+        body: Box::new(block),
+        else_body,
+    };
+    Ok(with_span(span, node))
+}
+
+fn build_ast_from_expression(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
+    let node = match pair.as_rule() {
+        Rule::Expression => {
+            let mut pair = pair.into_inner();
+            let mut expr = build_ast_from_expression(child(&mut pair, span)?)?;
+            while let Some(next) = pair.next() {
+                expr = match next.as_rule() {
+                    Rule::RangeOperator => {
+                        let end = build_ast_from_expression(child(&mut pair, span)?)?;
+                        let step = match pair.peek() {
+                            Some(step) if step.as_rule() != Rule::Ternary => {
+                                Some(Box::new(build_ast_from_expression(child(&mut pair, span)?)?))
+                            }
+                            _ => None,
+                        };
+                        AstNode::Range {
+                            start: Box::new(expr),
+                            end: Box::new(end),
+                            inclusive: next.as_str() == "..=",
+                            step,
+                        }
+                    }
+                    Rule::Ternary => {
+                        let ternary_span = span_of(&next);
+                        let mut branches = next.into_inner();
+                        let then = build_ast_from_expression(child(&mut branches, ternary_span)?)?;
+                        let otherwise = build_ast_from_expression(child(&mut branches, ternary_span)?)?;
+                        AstNode::IfStatement {
+                            condition: Box::new(expr),
+                            body: Box::new(then),
+                            else_body: Some(Box::new(otherwise)),
+                        }
+                    }
+                    _ => return Err(unexpected(&next)),
+                };
+            }
+            AstNode::Expression(Box::new(expr))
+        }
+        Rule::Term => build_ast_from_term(pair)?,
+        Rule::Number => parse_number(pair)?,
         Rule::UnaryExpression => {
             let mut pair = pair.into_inner();
-            let op = pair.next().unwrap();
-            let child = pair.next().unwrap();
-            let child = build_ast_from_term(child);
+            let op = child(&mut pair, span)?;
+            let child = build_ast_from_term(child(&mut pair, span)?)?;
 
-            parse_unary_expression(op, child)
+            parse_unary_expression(op, child)?
         }
         Rule::BinaryExpression => {
             let mut pair = pair.into_inner();
-            let lhs = pair.next().unwrap();
-            let mut lhs = build_ast_from_term(lhs);
-            let op = pair.next().unwrap();
-            let rhs = pair.next().unwrap();
-            let mut rhs = build_ast_from_term(rhs);
-
-            let mut retval = parse_binary_expression(lhs, op, rhs);
-            loop {
-                let pair_buf = pair.next();
-                if let Some(op) = pair_buf {
-                    lhs = retval;
-                    rhs = build_ast_from_term(pair.next().unwrap());
-                    retval = parse_binary_expression(lhs, op, rhs);
-                } else {
-                    return retval;
-                }
+            let mut operands = vec![build_ast_from_term(child(&mut pair, span)?)?];
+            let mut operators = vec![];
+            while let Some(op) = pair.next() {
+                operators.push(parse_binary_operator(op)?);
+                operands.push(build_ast_from_term(child(&mut pair, span)?)?);
             }
+
+            fold_binary_expression(operands, operators)
+                .ok_or_else(|| internal_error(span, "a binary expression is missing an operand"))?
         }
-        Rule::FuncCall => build_ast_from_function_call(pair),
+        Rule::FuncCall => build_ast_from_function_call(pair)?,
         Rule::Identifier => AstNode::Identifier(pair.as_str().to_string()),
         Rule::String => {
             let string_lit = pair.as_str();
-            AstNode::Str(string_lit[1..string_lit.len() - 1].to_string())
+            let contents = string_lit.strip_prefix('"').and_then(|s| s.strip_suffix('"'));
+            AstNode::Str(contents.unwrap_or(string_lit).to_string())
         }
         Rule::Boolean => match pair.as_str() {
             "True" => AstNode::Boolean(true),
             "False" => AstNode::Boolean(false),
-            unknown => panic!("Unknown boolean: {:?}", unknown),
+            _ => return Err(unexpected(&pair)),
         },
-        unknown => panic!("Unknown expression: {:?}", unknown),
-    }
+        Rule::Nil => AstNode::Nil,
+        Rule::IfStatement => build_ast_from_if(pair)?,
+        Rule::Block => build_ast_from_block(pair)?,
+        Rule::List => AstNode::List(
+            pair.into_inner()
+                .map(|item| build_ast_from_expression(item).map(Box::new))
+                .collect::<Built<_>>()?,
+        ),
+        _ => return Err(unexpected(&pair)),
+    };
+    Ok(with_span(span, node))
 }
 
-fn build_ast_from_term(pair: Pair<Rule>) -> AstNode {
-    match pair.as_rule() {
-        Rule::Term => AstNode::Term(Box::new(build_ast_from_term(
-            pair.into_inner().next().unwrap(),
-        ))),
-        Rule::Number => parse_number(pair),
-        Rule::Expression => build_ast_from_expression(pair),
-	Rule::FuncCall => build_ast_from_function_call(pair),
-        Rule::Identifier => AstNode::Str(pair.as_str().to_string()),
-        unknown => panic!("Unknown term: {:?}", unknown),
+fn build_ast_from_term(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
+    let node = match pair.as_rule() {
+        Rule::Term => {
+            let mut pair = pair.into_inner();
+            let mut term = build_ast_from_term(child(&mut pair, span)?)?;
+            for postfix in pair {
+                term = match postfix.as_rule() {
+                    Rule::Propagate => AstNode::Propagate(Box::new(term)),
+                    Rule::Index => {
+                        let index_span = span_of(&postfix);
+                        AstNode::Index {
+                            target: Box::new(term),
+                            index: Box::new(build_ast_from_expression(child(
+                                &mut postfix.into_inner(),
+                                index_span,
+                            )?)?),
+                        }
+                    }
+                    _ => return Err(unexpected(&postfix)),
+                };
+            }
+            AstNode::Term(Box::new(term))
+        }
+        Rule::Expression
+        | Rule::Number
+        | Rule::String
+        | Rule::Boolean
+        | Rule::Nil
+        | Rule::List
+        | Rule::IfStatement
+        | Rule::Block
+        | Rule::FuncCall
+        | Rule::Identifier => build_ast_from_expression(pair)?,
+        _ => return Err(unexpected(&pair)),
+    };
+    Ok(with_span(span, node))
+}
+
+fn build_ast_from_assign_target(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
+    let mut pair = pair.into_inner();
+    let mut target = AstNode::Identifier(child(&mut pair, span)?.as_str().to_string());
+    for index in pair {
+        let index_span = span_of(&index);
+        target = AstNode::Index {
+            target: Box::new(target),
+            index: Box::new(build_ast_from_expression(child(&mut index.into_inner(), index_span)?)?),
+        };
     }
+    Ok(target)
 }
 
-fn build_ast_from_function_call(pair: Pair<Rule>) -> AstNode {
+fn build_ast_from_function_call(pair: Pair<Rule>) -> Built {
     match pair.as_rule() {
         Rule::FuncCall => {
+            let span = span_of(&pair);
             let mut pair = pair.into_inner();
-            let name = pair.next().unwrap().as_str();
-            let arg_list = build_ast_from_arg_list(pair.next().unwrap());
-            AstNode::FuncCall {
+            let name = child(&mut pair, span)?.as_str();
+            let arg_list = build_ast_from_arg_list(child(&mut pair, span)?)?;
+            let call = AstNode::FuncCall {
                 name: name.to_string(),
                 args: Box::new(arg_list),
-            }
+            };
+            Ok(with_span(span, call))
         }
-        unknown => panic!("Unknown function call: {:?}", unknown),
+        _ => Err(unexpected(&pair)),
+    }
+}
+
+/// The source `pair` covers, without the whitespace some rules include at
+/// either end.
+fn span_of(pair: &Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    let text = span.as_str();
+    let start = span.start() + (text.len() - text.trim_start().len());
+    let end = (span.end() - (text.len() - text.trim_end().len())).max(start);
+    let (line, col) = line_col(span.get_input(), start);
+    Span { start, end, line, col }
+}
+
+/// The 1-based line and column of byte `pos` in `text`.
+fn line_col(text: &str, pos: usize) -> (usize, usize) {
+    pest::Position::new(text, pos).map_or((1, 1), |position| position.line_col())
+}
+
+/// Wraps `node` in `AstNode::Spanned`, unless a nested builder already did for
+/// the same source.
+fn with_span(span: Span, node: AstNode) -> AstNode {
+    match node {
+        AstNode::Spanned(..) => node,
+        node => AstNode::Spanned(span, Box::new(node)),
     }
 }
 
-fn build_ast_from_arg_list(pair: Pair<Rule>) -> AstNode {
+fn build_ast_from_arg_list(pair: Pair<Rule>) -> Built {
     let mut args: Vec<Box<AstNode>> = vec![];
     let arg_pairs = pair.into_inner();
 
     for arg in arg_pairs {
-        args.push(Box::new(build_ast_from_expression(arg)));
+        let arg = match arg.as_rule() {
+            Rule::NamedArgument => {
+                let span = span_of(&arg);
+                let mut arg = arg.into_inner();
+                let name = child(&mut arg, span)?.as_str().to_string();
+                let value = build_ast_from_expression(child(&mut arg, span)?)?;
+                AstNode::NamedArg {
+                    name,
+                    value: Box::new(value),
+                }
+            }
+            _ => build_ast_from_expression(arg)?,
+        };
+        args.push(Box::new(arg));
     }
-    AstNode::ArgList(args)
+    Ok(AstNode::ArgList(args))
 }
 
-fn parse_func_def(pair: Pair<Rule>) -> AstNode {
+fn parse_func_def(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
     let mut pair = pair.into_inner();
-    let ident = pair.next().unwrap().as_str();
-    let args = pair.next().unwrap();
-    let body = pair.next().unwrap();
-    AstNode::FuncDef {
+    let ident = child(&mut pair, span)?.as_str();
+    let args = child(&mut pair, span)?;
+    let body = child(&mut pair, span)?;
+    let node = AstNode::FuncDef {
         name: ident.to_string(),
-        args: Box::new(parse_def_arg_list(args)),
-        body: Box::new(build_ast_from_block(body)),
-    }
+        args: Box::new(parse_def_arg_list(args)?),
+        body: Box::new(build_ast_from_block(body)?),
+    };
+    Ok(with_span(span, node))
 }
 
-fn parse_def_arg_list(pair: Pair<Rule>) -> AstNode {
+fn parse_def_arg_list(pair: Pair<Rule>) -> Built {
     let mut args = vec![];
     let arg_pairs = pair.into_inner();
 
     for arg in arg_pairs {
-        args.push(arg.as_str().to_string());
+        let mut rest = false;
+        let mut name = String::new();
+        let mut default = None;
+        for part in arg.into_inner() {
+            match part.as_rule() {
+                Rule::RestMarker => rest = true,
+                Rule::Identifier => name = part.as_str().to_string(),
+                _ => default = Some(Box::new(build_ast_from_expression(part)?)),
+            }
+        }
+        args.push(Parameter { name, default, rest });
     }
-    AstNode::DefArgList(args)
+    Ok(AstNode::DefArgList(args))
 }
 
-fn parse_number(pair: Pair<Rule>) -> AstNode {
-    let value: i32 = pair.as_str().parse().unwrap();
-    AstNode::Int(value)
+fn parse_number(pair: Pair<Rule>) -> Built {
+    match pair.as_str().parse() {
+        Ok(value) => Ok(AstNode::Int(value)),
+        Err(_) => Err(ParseError {
+            kind: ParseErrorKind::IntegerTooLarge,
+            found: Some(pair.as_str().to_string()),
+            span: span_of(&pair),
+        }),
+    }
 }
 
-fn parse_for_params(pair: Pair<Rule>) -> AstNode {
+fn parse_for_params(pair: Pair<Rule>) -> Built {
+    let span = span_of(&pair);
     let mut pair = pair.into_inner();
-    AstNode::ForLoopParams {
-        initialization: Box::new(build_ast_from_statement(pair.next().unwrap())),
-        condition: Box::new(build_ast_from_expression(pair.next().unwrap())),
-        updater: Box::new(build_ast_from_statement(pair.next().unwrap())),
-    }
+    Ok(AstNode::ForLoopParams {
+        initialization: Box::new(build_ast_from_statement(child(&mut pair, span)?)?),
+        condition: Box::new(build_ast_from_expression(child(&mut pair, span)?)?),
+        updater: Box::new(build_ast_from_statement(child(&mut pair, span)?)?),
+    })
 }
 
-fn parse_unary_expression(pair: Pair<Rule>, child: AstNode) -> AstNode {
-    AstNode::UnaryExpression {
-        op: parse_unary_operator(pair),
+fn parse_unary_expression(pair: Pair<Rule>, child: AstNode) -> Built {
+    Ok(AstNode::UnaryExpression {
+        op: parse_unary_operator(pair)?,
         child: Box::new(child),
-    }
+    })
 }
 
-fn parse_unary_operator(pair: Pair<Rule>) -> UnaryOperator {
+fn parse_unary_operator(pair: Pair<Rule>) -> Built<UnaryOperator> {
     match pair.as_str() {
-        "!" => UnaryOperator::Not,
-        "-" => UnaryOperator::Minus,
-        unknown => panic!("Unknown rule: {:?}", unknown),
+        "!" => Ok(UnaryOperator::Not),
+        "-" => Ok(UnaryOperator::Minus),
+        _ => Err(unexpected(&pair)),
     }
 }
 
-fn parse_binary_expression(lhs: AstNode, op: Pair<Rule>, rhs: AstNode) -> AstNode {
-    AstNode::BinaryExpression {
+/// Folds `t0 op1 t1 op2 t2 ...` into a tree honouring `BinaryOperator::precedence`,
+/// associating to the left within a precedence level. `None` if an operator is
+/// missing an operand.
+fn fold_binary_expression(operands: Vec<AstNode>, operators: Vec<BinaryOperator>) -> Option<AstNode> {
+    let mut output: Vec<AstNode> = vec![];
+    let mut pending: Vec<BinaryOperator> = vec![];
+    let mut operands = operands.into_iter();
+    output.push(operands.next()?);
+
+    for (op, rhs) in operators.into_iter().zip(operands) {
+        while let Some(top) = pending.pop_if(|top| top.precedence() >= op.precedence()) {
+            reduce_binary_expression(&mut output, top)?;
+        }
+        pending.push(op);
+        output.push(rhs);
+    }
+    while let Some(op) = pending.pop() {
+        reduce_binary_expression(&mut output, op)?;
+    }
+
+    output.pop()
+}
+
+fn reduce_binary_expression(output: &mut Vec<AstNode>, op: BinaryOperator) -> Option<()> {
+    let rhs = output.pop()?;
+    let lhs = output.pop()?;
+    let span = lhs.span().zip(rhs.span()).map(|(lhs, rhs)| lhs.to(rhs));
+    let node = AstNode::BinaryExpression {
         lhs: Box::new(lhs),
-        op: parse_binary_operator(op),
+        op,
         rhs: Box::new(rhs),
-    }
+    };
+    output.push(match span {
+        Some(span) => with_span(span, node),
+        None => node,
+    });
+    Some(())
 }
 
-fn parse_binary_operator(pair: Pair<Rule>) -> BinaryOperator {
-    match pair.as_str() {
+fn parse_binary_operator(pair: Pair<Rule>) -> Built<BinaryOperator> {
+    parse_binary_operator_str(pair.as_str(), span_of(&pair))
+}
+
+fn parse_binary_operator_str(op: &str, span: Span) -> Built<BinaryOperator> {
+    Ok(match op {
         "+" => BinaryOperator::Add,
         "-" => BinaryOperator::Subtract,
         "*" => BinaryOperator::Multiply,
         "/" => BinaryOperator::Divide,
+        "%" => BinaryOperator::Modulo,
         ">" => BinaryOperator::Greater,
         "<" => BinaryOperator::Less,
         "==" => BinaryOperator::Equal,
         "!=" => BinaryOperator::NotEqual,
         ">=" => BinaryOperator::GreaterEq,
         "<=" => BinaryOperator::LessEq,
-        unknown => panic!("Unknown rule: {:?}", unknown),
-    }
+        "??" => BinaryOperator::NullCoalesce,
+        unknown => return Err(internal_error(span, format!("unknown operator `{unknown}`"))),
+    })
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    /// Every node of `ast`, `ast` included.
+    fn all_nodes(ast: &AstNode) -> Vec<&AstNode> {
+        let mut nodes = vec![];
+        let mut pending = vec![ast];
+        while let Some(node) = pending.pop() {
+            nodes.push(node);
+            pending.extend(node.children());
+        }
+        nodes
+    }
+
     #[test]
     fn e2e_functions() {}
+
+    #[test]
+    fn block_statements_need_no_semicolon() {
+        let source = "fn main() {
+            if True { print(1); } else { print(2); }
+            while False { }
+            for x in [1, 2,] { print(x) ; }
+            for (let i = 0; i < 3; i += 1) { }
+            try { } finally { }
+            { let scoped = 1; }
+            if True { };
+        }";
+        assert!(parse(source).is_ok());
+    }
+
+    #[test]
+    fn empty_program_and_blocks() {
+        assert!(matches!(parse("").unwrap(), AstNode::Program(defs) if defs.is_empty()));
+        assert!(parse("  \n ").is_ok());
+        assert!(parse("fn main() {}").is_ok());
+    }
+
+    #[test]
+    fn trailing_commas() {
+        assert!(parse("fn f(a, b,) { } fn main() { f(1, 2,); f( 1 ); }").is_ok());
+        assert!(parse("fn f(a,,) { }").is_err());
+        assert!(parse("fn main() { f(,); }").is_err());
+    }
+
+    #[test]
+    fn simple_statements_still_need_semicolons() {
+        assert!(parse("fn main() { let a = 1 let b = 2; }").is_err());
+    }
+
+    #[test]
+    fn trailing_if_is_the_block_value() {
+        let ast = parse("fn f() { if True { 1 } else { 2 } }").unwrap();
+        let AstNode::Program(defs) = ast else { panic!() };
+        let AstNode::FuncDef { body, .. } = defs[0].unspanned() else { panic!() };
+        assert!(matches!(body.unspanned(), AstNode::Block { statements, tail: Some(_) } if statements.is_empty()));
+    }
+
+    #[test]
+    fn rejects_integer_literals_that_overflow() {
+        let errors = parse("fn main() {\n    return 2147483648;\n}").unwrap_err();
+        assert_eq!(errors[0].kind, ParseErrorKind::IntegerTooLarge);
+        assert_eq!(errors[0].to_string(), "2:12: integer literal `2147483648` is too large");
+        assert!(parse("fn main() { return 2147483647; }").is_ok());
+    }
+
+    #[test]
+    fn none_is_a_whole_word() {
+        let ast = parse("fn main() { return [None, None_x, Nonexistent]; }").unwrap();
+        let mut names: Vec<&str> = all_nodes(&ast)
+            .into_iter()
+            .filter_map(|node| match node {
+                AstNode::Nil => Some("None"),
+                AstNode::Identifier(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["None", "None_x", "Nonexistent"]);
+    }
+
+    #[test]
+    fn booleans_are_whole_words() {
+        let ast = parse("fn main() { let Trueish = 1; return [True, Trueish, False, Falsey, False_]; }").unwrap();
+        let mut names: Vec<&str> = all_nodes(&ast)
+            .into_iter()
+            .filter_map(|node| match node {
+                AstNode::Boolean(b) => Some(if *b { "True" } else { "False" }),
+                AstNode::Identifier(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["False", "False_", "Falsey", "True", "Trueish"]);
+    }
+
+    #[test]
+    fn nodes_carry_spans() {
+        let source = "fn main() {\n    let total = 1 + two(3);\n}";
+        let ast = parse(source).unwrap();
+        let AstNode::Program(defs) = &ast else { panic!() };
+        assert_eq!(defs[0].span().map(|span| (span.start, span.end)), Some((0, source.len())));
+
+        let spans: Vec<_> = all_nodes(&ast)
+            .into_iter()
+            .filter_map(|node| node.span())
+            .map(|span| (span.line, span.col, &source[span.start..span.end]))
+            .collect();
+        assert!(spans.contains(&(2, 5, "let total = 1 + two(3)")));
+        assert!(spans.contains(&(2, 17, "1 + two(3)")));
+        assert!(spans.contains(&(2, 21, "two(3)")));
+        assert!(spans.contains(&(2, 25, "3")));
+    }
+
+    #[test]
+    fn skips_comments() {
+        let source = "// a program\nfn main() { // entry\n    let s = \"// not a comment\"; // (\n}\n// the end";
+        let ast = parse(source).unwrap();
+        let AstNode::Program(defs) = &ast else { panic!() };
+        assert_eq!(defs[0].span().map(|span| span.line), Some(2));
+        let comments: Vec<&str> = comments(source).into_iter().map(|range| &source[range]).collect();
+        assert_eq!(comments, ["// a program", "// entry", "// (", "// the end"]);
+        assert_eq!(syntax_errors("fn main() { // {\n    let x = ;\n}"), vec!["2:13: expected an expression, found `;`"]);
+    }
+
+    #[test]
+    fn rejects_code_nested_too_deeply() {
+        let parens = |n: usize| format!("fn main() {{ return {}1{}; }}", "(".repeat(n), ")".repeat(n));
+        assert!(parse(&parens(MAX_NESTING - 1)).is_ok());
+        let errors = parse(&parens(3000)).unwrap_err();
+        assert_eq!(errors[0].kind, ParseErrorKind::TooDeeplyNested);
+        assert_eq!(errors[0].to_string(), format!("1:{}: code is nested too deeply", 19 + MAX_NESTING));
+
+        let ternaries = format!("fn main() {{ return {}1{}; }}", "True ? ".repeat(3000), " : 0".repeat(3000));
+        assert_eq!(parse(&ternaries).unwrap_err()[0].kind, ParseErrorKind::TooDeeplyNested);
+        let else_ifs = format!("fn main() {{ if True {{ }} {}}}", "else if True { } ".repeat(3000));
+        assert_eq!(parse(&else_ifs).unwrap_err()[0].kind, ParseErrorKind::TooDeeplyNested);
+        let sum = format!("fn main() {{ return 1{}; }}", " + 1".repeat(MAX_TREE_DEPTH));
+        assert_eq!(parse(&sum).unwrap_err()[0].kind, ParseErrorKind::TooDeeplyNested);
+
+        // Long runs that do not nest are fine.
+        let flat = format!("fn main() {{ {} print({}); }}", "if True { } else { } ".repeat(500), ["True ? 1 : 2"; 300].join(", "));
+        assert!(parse(&flat).is_ok());
+    }
+
+    fn syntax_errors(source: &str) -> Vec<String> {
+        parse(source).unwrap_err().into_iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let source = "fn main() {
+    let a = 1
+    let b = 2;
+    print(a b);
+    let c = ;
+    print(\"still parsed\");
+}
+fn broken(a b) { }
+fn later() { x + ; }";
+        assert_eq!(
+            syntax_errors(source),
+            vec![
+                "3:5: expected `;` after statement, found `let`",
+                "4:13: expected `,` or `)`, found `b`",
+                "5:13: expected an expression, found `;`",
+                "8:11: expected parameters like `(a, b = 1, ...rest)`, found `a`",
+                "9:18: expected an expression, found `;`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unclosed_blocks_and_stray_tokens() {
+        assert_eq!(syntax_errors("fn main() { if x { }"), vec!["1:21: expected `}`, found end of file"]);
+        assert_eq!(syntax_errors("fn main() { } }"), vec!["1:15: expected a function definition, found `}`"]);
+        assert_eq!(syntax_errors("let x = 1;"), vec!["1:1: expected a function definition, found `let`"]);
+        assert_eq!(syntax_errors("fn main() { f(1,; }"), vec!["1:17: expected an expression, found `;`"]);
+
+        let errors = parse("fn main() { let a = [1 2]; f(a; }").unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ParseErrorKind::MissingSeparator { closing: ']' },
+                ParseErrorKind::MissingSeparator { closing: ')' },
+            ]
+        );
+    }
 }
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::ast::AstNode;
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::{Evaluator, RuntimeError, Value};
+use crate::parser::{ParseError, ParseErrorKind};
+use crate::{checker, parser};
+
+const HELP: &str = "\
+Enter function definitions, statements or an expression to print its value.
+Input continues on the next line while brackets are unclosed.
+
+  :help         show this message
+  :env          list the variables and functions defined so far
+  :load <file>  define the functions in a file, without running main
+  :reset        forget all definitions
+  Ctrl-D        quit";
+
+/// An interactive session: one `Evaluator` that every input is run in, so
+/// variables and functions persist between them.
+pub struct Repl {
+    evaluator: Evaluator,
+    max_depth: usize,
+    color: bool,
+    /// How many inputs of code have been entered; the `n`th is `<repl:n>` in
+    /// error reports.
+    inputs: usize,
+    /// Where each function was defined: `<repl:n>` or a loaded file's path.
+    defined_in: HashMap<String, String>,
+}
+
+impl Repl {
+    pub fn new(max_depth: usize, color: bool) -> Self {
+        Repl {
+            evaluator: Evaluator::new().with_max_depth(max_depth),
+            max_depth,
+            color,
+            inputs: 0,
+            defined_in: HashMap::new(),
+        }
+    }
+
+    /// Handles one complete input, a `:command` or cheese code. `Ok` holds what
+    /// to print on stdout, `Err` a report for stderr.
+    pub fn handle(&mut self, input: &str) -> Result<Option<String>, String> {
+        let input = input.trim();
+        let Some(command) = input.strip_prefix(':') else {
+            return self.eval(input);
+        };
+        let (command, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        match (command, argument.trim()) {
+            ("help", _) => Ok(Some(HELP.to_string())),
+            ("env", _) => {
+                let globals = self.evaluator.globals();
+                let lines: Vec<String> = globals.iter().map(|(name, value)| format!("{} = {}", name, value.repr())).collect();
+                Ok((!lines.is_empty()).then(|| lines.join("\n")))
+            }
+            ("load", "") => Err("Usage: :load <file>".to_string()),
+            ("load", path) => self.load(path),
+            ("reset", _) => {
+                self.evaluator = Evaluator::new().with_max_depth(self.max_depth);
+                self.defined_in.clear();
+                Ok(None)
+            }
+            _ => Err(format!("Unknown command :{}, try :help", command)),
+        }
+    }
+
+    /// The code passed to `exit()`, which ends the session.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.evaluator.exit_status()
+    }
+
+    fn eval(&mut self, input: &str) -> Result<Option<String>, String> {
+        self.inputs += 1;
+        let file = format!("<repl:{}>", self.inputs);
+        let ast = parser::parse_repl_input(input).map_err(|errors| self.render(&file, input, &errors))?;
+        checker::check(&ast).map_err(|errors| self.render(&file, input, &errors))?;
+        self.record_definitions(&ast, &file);
+        match self.evaluator.eval_global(&ast) {
+            Ok(_) if self.exit_status().is_some() => Ok(None),
+            Ok(Value::Nil) => Ok(None),
+            Ok(value) => Ok(Some(value.repr())),
+            Err(error) => Err(self.report_runtime_error(&file, input, error)),
+        }
+    }
+
+    fn load(&mut self, path: &str) -> Result<Option<String>, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let ast = parser::parse(&source).map_err(|errors| self.render(path, &source, &errors))?;
+        checker::check(&ast).map_err(|errors| self.render(path, &source, &errors))?;
+        self.record_definitions(&ast, path);
+        self.evaluator.eval_global(&ast).map_err(|error| self.report_runtime_error(path, &source, error))?;
+        Ok(Some(format!("Loaded {}", path)))
+    }
+
+    /// Notes `file` as where the functions `ast` defines come from.
+    fn record_definitions(&mut self, ast: &AstNode, file: &str) {
+        for node in ast.children() {
+            if let AstNode::FuncDef { name, .. } = node.unspanned() {
+                self.defined_in.insert(name.clone(), file.to_string());
+            }
+        }
+    }
+
+    fn render<'a, E>(&self, file: &str, source: &str, errors: &'a [E]) -> String
+    where
+        Diagnostic: From<&'a E>,
+    {
+        errors.iter().map(|error| Diagnostic::from(error).render(file, source, self.color)).collect()
+    }
+
+    /// Reports an error raised by `input`, read from `file`. Positions inside a
+    /// function are in whichever input or file defined it, so each frame of
+    /// the backtrace names its own, and the source snippet is only shown for
+    /// positions in `input`.
+    fn report_runtime_error(&self, file: &str, input: &str, error: RuntimeError) -> String {
+        let file_of = |function: &str| self.defined_in.get(function).cloned().unwrap_or_else(|| file.to_string());
+        let mut diagnostic = Diagnostic::from(&error);
+        if error.stack.last().is_some_and(|frame| file_of(&frame.function) != file) {
+            diagnostic.primary = None;
+        }
+        let mut report = diagnostic.render(file, input, self.color);
+        report.push_str(&error.backtrace_in(file_of));
+        report
+    }
+}
+
+/// Whether `input` stops inside an unclosed bracket, so the REPL should read
+/// another line before running it.
+pub fn is_incomplete(input: &str) -> bool {
+    let unclosed = |errors: Vec<ParseError>| {
+        errors.iter().any(|error| matches!(error.kind, ParseErrorKind::UnclosedDelimiter { .. }))
+    };
+    !input.trim_start().starts_with(':') && parser::parse_repl_input(input).is_err_and(unclosed)
+}
+
+/// Where history is kept between sessions.
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".firstlang_history"))
+}
+
+/// Reads inputs from the terminal until end of file or `exit()`, returning
+/// the exit status.
+pub fn run(max_depth: usize, color: bool) -> rustyline::Result<i32> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_file();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    println!("firstlang {}, :help for help", env!("CARGO_PKG_VERSION"));
+    let mut repl = Repl::new(max_depth, color);
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if is_incomplete(&input) {
+                    continue;
+                }
+                let _ = editor.add_history_entry(input.trim_end());
+                match repl.handle(&input) {
+                    Ok(Some(output)) => println!("{}", output),
+                    Ok(None) => {}
+                    Err(report) => eprintln!("{}", report.trim_end()),
+                }
+                input.clear();
+                if repl.exit_status().is_some() {
+                    break;
+                }
+            }
+            // Ctrl-C abandons the current input, Ctrl-D ends the session.
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error),
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(repl.exit_status().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::Span;
+    use crate::interpreter::ErrorKind;
+
+    #[test]
+    fn keeps_definitions_between_inputs() {
+        let mut repl = Repl::new(10, false);
+        assert_eq!(repl.handle("let x = 40;"), Ok(None));
+        assert_eq!(repl.handle("fn add(a, b) { return a + b; }"), Ok(None));
+        assert_eq!(repl.handle("add(x, 2)"), Ok(Some("42".to_string())));
+        assert_eq!(repl.handle("x += 1; str(x)"), Ok(Some("\"41\"".to_string())));
+        assert_eq!(repl.handle(":env"), Ok(Some("add = <fn add>\nx = 41".to_string())));
+
+        assert_eq!(repl.handle(":reset"), Ok(None));
+        assert_eq!(repl.handle(":env"), Ok(None));
+        assert!(repl.handle("x").unwrap_err().contains("error[E0012]"));
+    }
+
+    #[test]
+    fn reports_errors_and_recovers() {
+        let mut repl = Repl::new(10, false);
+        let report = repl.handle("let y = * 2;").unwrap_err();
+        assert!(report.contains("1 | let y = * 2;"));
+        assert!(repl.handle("fn boom() { return 1 / 0; }").is_ok());
+        // boom() is defined by the second input and called by the third.
+        let report = repl.handle("boom()").unwrap_err();
+        assert!(report.contains("at boom() (<repl:2>:1:20)"));
+        assert!(!report.contains(" | "));
+        let report = repl.handle("fn again() { let x = boom(); } again()").unwrap_err();
+        assert!(report.contains("at boom() (<repl:2>:1:20)\n    at again() (<repl:4>:1:22)"));
+        let report = repl.handle("fn here() { return 1 / 0; } here()").unwrap_err();
+        assert!(report.contains("1 | fn here() { return 1 / 0; } here()"));
+        assert_eq!(repl.handle("1 + 1"), Ok(Some("2".to_string())));
+        assert!(repl.handle(":nope").is_err());
+        assert_eq!(repl.exit_status(), None);
+        assert_eq!(repl.handle("exit(4)"), Ok(None));
+        assert_eq!(repl.exit_status(), Some(4));
+    }
+
+    #[test]
+    fn waits_for_unclosed_brackets() {
+        assert!(is_incomplete("fn f() {\n"));
+        assert!(is_incomplete("print(1,\n"));
+        assert!(!is_incomplete("fn f() { }\n"));
+        assert!(!is_incomplete("let x = ;\n"));
+        assert!(!is_incomplete(":load (\n"));
+    }
+
+    #[test]
+    fn loads_files() {
+        let mut repl = Repl::new(10, false);
+        assert_eq!(repl.handle(":load examples/fib.cheese"), Ok(Some("Loaded examples/fib.cheese".to_string())));
+        assert!(repl.handle("main").unwrap().is_some_and(|main| main == "<fn main>"));
+        assert!(repl.handle(":load missing.cheese").is_err());
+
+        let mut error = RuntimeError::new(ErrorKind::DivisionByZero, "Division by zero");
+        error.location = Some(Span { start: 0, end: 5, line: 1, col: 1 });
+        let report = repl.report_runtime_error("lib.cheese", "1 / 0", error);
+        assert!(report.contains("lib.cheese:1:1") && !report.contains("<repl>"));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::ast::AstNode;
+use crate::interpreter::{ErrorKind, Evaluator, RuntimeError};
+
+/// Functions named like this are run by `firstlang test`.
+pub const TEST_PREFIX: &str = "test_";
+
+/// The outcome of one test function: it passes unless it raises an error or
+/// calls `exit()` with a non-zero status.
+pub struct TestResult {
+    pub name: String,
+    pub result: Result<(), RuntimeError>,
+}
+
+/// Runs every `test_` function in `program`, in the order they are defined,
+/// each in a fresh evaluator.
+pub fn run_tests(program: &AstNode, max_depth: usize) -> Vec<TestResult> {
+    program
+        .children()
+        .into_iter()
+        .filter_map(|node| match node.unspanned() {
+            AstNode::FuncDef { name, .. } if name.starts_with(TEST_PREFIX) => Some(name),
+            _ => None,
+        })
+        .map(|name| TestResult {
+            name: name.clone(),
+            result: run_test(program, name, max_depth),
+        })
+        .collect()
+}
+
+fn run_test(program: &AstNode, name: &str, max_depth: usize) -> Result<(), RuntimeError> {
+    let mut evaluator = Evaluator::new().with_max_depth(max_depth);
+    evaluator.run_entry(program, name)?;
+    match evaluator.exit_status() {
+        Some(code) if code != 0 => Err(RuntimeError::new(
+            ErrorKind::Custom("Exit".to_string()),
+            format!("{}() called exit({})", name, code),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn runs_test_functions_in_order() {
+        let source = "fn test_ok() { let x = 1 + 1; }
+        fn helper() { return 1; }
+        fn test_fails() { throw error(\"ValueError\", \"nope\"); }
+        fn test_needs_args(a, b) { }
+        fn test_exits() { exit(1); }
+        fn test_exits_cleanly() { exit(0); }";
+        let results = run_tests(&parser::parse(source).unwrap(), 10);
+        let names: Vec<&str> = results.iter().map(|test| test.name.as_str()).collect();
+        assert_eq!(names, ["test_ok", "test_fails", "test_needs_args", "test_exits", "test_exits_cleanly"]);
+        assert!(results[0].result.is_ok());
+        assert!(results[1].result.as_ref().is_err_and(|error| error.message == "nope"));
+        assert!(results[2].result.as_ref().is_err_and(|error| error.kind == ErrorKind::InvalidMain));
+        assert!(results[3].result.as_ref().is_err_and(|error| error.message == "test_exits() called exit(1)"));
+        assert!(results[4].result.is_ok());
+    }
+}